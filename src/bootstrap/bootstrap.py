@@ -815,6 +815,14 @@ def bootstrap(help_triggered):
     env["BOOTSTRAP_PARENT_ID"] = str(os.getpid())
     env["BOOTSTRAP_PYTHON"] = sys.executable
     env["BUILD_DIR"] = build.build_dir
+    if any(arg == '--sanity-only' or arg.startswith('--sanity-json')
+           for arg in sys.argv):
+        # The exit code says what the sanity check found, so pass it on.
+        sys.stdout.flush()
+        code = subprocess.call(args, env=env)
+        if code != 0:
+            sys.exit(code)
+        return
     run(args, env=env, verbose=build.verbose)
 
 
@@ -92,7 +92,7 @@ impl Step for Rustc {
         builder.clear_if_dirty(&stage_out, &libtest_stamp(builder, compiler, target));
 
         let mut cargo = builder.cargo(compiler, Mode::Rustc, target, "check");
-        rustc_cargo(builder, &mut cargo);
+        rustc_cargo(builder, target, &mut cargo);
 
         let _folder = builder.fold_output(|| format!("stage{}-rustc", compiler.stage));
         println!("Checking compiler artifacts ({} -> {})", &compiler.host, target);
@@ -138,7 +138,7 @@ impl Step for CodegenBackend {
         let backend = self.backend;
 
         let mut cargo = builder.cargo(compiler, Mode::Codegen, target, "check");
-        let features = builder.rustc_features().to_string();
+        let features = builder.rustc_features(target).to_string();
         cargo.arg("--manifest-path").arg(builder.src.join("src/librustc_codegen_llvm/Cargo.toml"));
         rustc_cargo_env(builder, &mut cargo);
 
@@ -70,6 +70,12 @@ pub struct Config {
     pub cmd: Subcommand,
     pub incremental: bool,
     pub dry_run: bool,
+    pub sanity_diff: bool,
+    pub list_checks: bool,
+    pub sanity_plan: bool,
+    pub sanity_only: bool,
+    pub sanity_export: Option<PathBuf>,
+    pub sanity_json: Option<PathBuf>,
 
     pub deny_warnings: bool,
     pub backtrace_on_ice: bool,
@@ -129,6 +135,18 @@ pub struct Config {
     pub test_miri: bool,
     pub save_toolstates: Option<PathBuf>,
     pub print_step_timings: bool,
+    pub print_sanity_timings: bool,
+    pub require_optional_tools: bool,
+    pub deny_sanity_warnings: Vec<String>,
+    pub sanity_probes: bool,
+    pub sanity_profile: Option<String>,
+    pub tools_bin: Option<PathBuf>,
+    pub stage0: Option<PathBuf>,
+    pub forbidden_tools: Vec<String>,
+    pub tool_checksums: HashMap<String, String>,
+    pub strict_path: bool,
+    pub raise_fd_limit: bool,
+    pub allow_root: bool,
 
     // Fallback musl-root for all targets
     pub musl_root: Option<PathBuf>,
@@ -167,6 +185,10 @@ pub struct Target {
     pub musl_root: Option<PathBuf>,
     pub qemu_rootfs: Option<PathBuf>,
     pub no_std: bool,
+    pub cc_max_version: Option<String>,
+    pub sdk: Option<PathBuf>,
+    /// The paths in `sdk` the target needs, as resolved by the sanity check.
+    pub sdk_paths: Vec<PathBuf>,
 }
 
 /// Structure of the `config.toml` file that configuration is read from.
@@ -216,6 +238,18 @@ struct Build {
     configure_args: Option<Vec<String>>,
     local_rebuild: Option<bool>,
     print_step_timings: Option<bool>,
+    print_sanity_timings: Option<bool>,
+    require_optional_tools: Option<bool>,
+    deny_sanity_warnings: Option<Vec<String>>,
+    sanity_probes: Option<bool>,
+    sanity_profile: Option<String>,
+    tools_bin: Option<String>,
+    stage0: Option<String>,
+    forbidden_tools: Option<Vec<String>>,
+    tool_checksums: Option<HashMap<String, String>>,
+    strict_path: Option<bool>,
+    raise_fd_limit: Option<bool>,
+    allow_root: Option<bool>,
 }
 
 /// TOML representation of various global install decisions.
@@ -327,6 +361,8 @@ struct TomlTarget {
     crt_static: Option<bool>,
     musl_root: Option<String>,
     qemu_rootfs: Option<String>,
+    cc_max_version: Option<String>,
+    sdk: Option<String>,
 }
 
 impl Config {
@@ -384,6 +420,12 @@ impl Config {
         config.cmd = flags.cmd;
         config.incremental = flags.incremental;
         config.dry_run = flags.dry_run;
+        config.sanity_diff = flags.sanity_diff;
+        config.list_checks = flags.list_checks;
+        config.sanity_plan = flags.sanity_plan;
+        config.sanity_only = flags.sanity_only || flags.sanity_json.is_some();
+        config.sanity_export = flags.sanity_export;
+        config.sanity_json = flags.sanity_json;
         config.keep_stage = flags.keep_stage;
         if let Some(value) = flags.warnings {
             config.deny_warnings = value;
@@ -443,6 +485,9 @@ impl Config {
         config.nodejs = build.nodejs.map(PathBuf::from);
         config.gdb = build.gdb.map(PathBuf::from);
         config.python = build.python.map(PathBuf::from);
+        config.tools_bin = build.tools_bin.map(PathBuf::from);
+        config.stage0 = build.stage0.map(PathBuf::from);
+        config.sanity_profile = build.sanity_profile;
         set(&mut config.low_priority, build.low_priority);
         set(&mut config.compiler_docs, build.compiler_docs);
         set(&mut config.docs, build.docs);
@@ -460,6 +505,15 @@ impl Config {
         set(&mut config.configure_args, build.configure_args);
         set(&mut config.local_rebuild, build.local_rebuild);
         set(&mut config.print_step_timings, build.print_step_timings);
+        set(&mut config.print_sanity_timings, build.print_sanity_timings);
+        set(&mut config.require_optional_tools, build.require_optional_tools);
+        set(&mut config.deny_sanity_warnings, build.deny_sanity_warnings);
+        set(&mut config.sanity_probes, build.sanity_probes);
+        set(&mut config.forbidden_tools, build.forbidden_tools);
+        set(&mut config.tool_checksums, build.tool_checksums);
+        set(&mut config.strict_path, build.strict_path);
+        set(&mut config.raise_fd_limit, build.raise_fd_limit);
+        set(&mut config.allow_root, build.allow_root);
         config.verbose = cmp::max(config.verbose, flags.verbose);
 
         if let Some(ref install) = toml.install {
@@ -571,6 +625,9 @@ impl Config {
                 if let Some(ref s) = cfg.android_ndk {
                     target.ndk = Some(config.src.join(s));
                 }
+                if let Some(ref s) = cfg.sdk {
+                    target.sdk = Some(config.src.join(s));
+                }
                 target.cc = cfg.cc.clone().map(PathBuf::from);
                 target.cxx = cfg.cxx.clone().map(PathBuf::from);
                 target.ar = cfg.ar.clone().map(PathBuf::from);
@@ -578,6 +635,7 @@ impl Config {
                 target.crt_static = cfg.crt_static.clone();
                 target.musl_root = cfg.musl_root.clone().map(PathBuf::from);
                 target.qemu_rootfs = cfg.qemu_rootfs.clone().map(PathBuf::from);
+                target.cc_max_version = cfg.cc_max_version.clone();
 
                 config.target_config.insert(INTERNER.intern_string(triple.clone()), target);
             }
@@ -42,6 +42,12 @@ pub struct Flags {
     pub exclude: Vec<PathBuf>,
     pub rustc_error_format: Option<String>,
     pub dry_run: bool,
+    pub sanity_diff: bool,
+    pub list_checks: bool,
+    pub sanity_plan: bool,
+    pub sanity_only: bool,
+    pub sanity_export: Option<PathBuf>,
+    pub sanity_json: Option<PathBuf>,
 
     // true => deny
     pub warnings: Option<bool>,
@@ -121,6 +127,17 @@ To learn more about a subcommand, run `./x.py <subcommand> -h`"
         opts.optmulti("", "exclude", "build paths to exclude", "PATH");
         opts.optopt("", "on-fail", "command to run on failure", "CMD");
         opts.optflag("", "dry-run", "dry run; don't build anything");
+        opts.optflag("", "sanity-diff",
+                     "print the configuration changes the sanity check would make and exit");
+        opts.optflag("", "list-checks", "list every sanity check and exit");
+        opts.optflag("", "sanity-plan",
+                     "print the sanity checks the configuration would run as JSON and exit");
+        opts.optflag("", "sanity-only",
+                     "run only the sanity check, exiting with a code saying what failed");
+        opts.optopt("", "sanity-export",
+                    "write the detected tools as a config.toml snippet to FILE", "FILE");
+        opts.optopt("", "sanity-json",
+                    "like --sanity-only, but also write the result as JSON to FILE", "FILE");
         opts.optopt("", "stage", "stage to build", "N");
         opts.optopt("", "keep-stage", "stage to keep without recompiling", "N");
         opts.optopt("", "src", "path to the root of the rust checkout", "DIR");
@@ -400,6 +417,12 @@ Arguments:
             verbose: matches.opt_count("verbose"),
             stage: matches.opt_str("stage").map(|j| j.parse().unwrap()),
             dry_run: matches.opt_present("dry-run"),
+            sanity_diff: matches.opt_present("sanity-diff"),
+            list_checks: matches.opt_present("list-checks"),
+            sanity_plan: matches.opt_present("sanity-plan"),
+            sanity_only: matches.opt_present("sanity-only"),
+            sanity_export: matches.opt_str("sanity-export").map(PathBuf::from),
+            sanity_json: matches.opt_str("sanity-json").map(PathBuf::from),
             on_fail: matches.opt_str("on-fail"),
             rustc_error_format: matches.opt_str("error-format"),
             keep_stage: matches.opt_str("keep-stage").map(|j| j.parse().unwrap()),
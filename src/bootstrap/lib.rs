@@ -195,6 +195,7 @@ mod job {
 }
 
 pub use config::Config;
+pub use sanity::{PathResolver, ToolResolver};
 use flags::Subcommand;
 use cache::{Interned, INTERNER};
 use toolstate::ToolState;
@@ -258,6 +259,13 @@ pub struct Build {
     // Probed tools at runtime
     lldb_version: Option<String>,
     lldb_python_dir: Option<String>,
+    // Why the directory `lldb -P` printed isn't used, if it isn't.
+    lldb_python_unusable: Option<String>,
+    nodejs_version: Option<String>,
+    sccache_version: Option<String>,
+    gdb_version: Option<String>,
+    // Why the debuginfo-gdb tests can't run with the gdb found, if they can't.
+    gdb_unsupported: Option<String>,
 
     // Runtime state filled in later on
     // C/C++ compilers and archiver for all targets
@@ -339,6 +347,55 @@ impl Build {
     ///
     /// By default all build output will be placed in the current directory.
     pub fn new(config: Config) -> Build {
+        Build::create(config, None)
+    }
+
+    /// Like `new`, but the sanity check finds tools through `resolver`
+    /// instead of searching `PATH`.
+    pub fn with_tool_resolver(config: Config, resolver: Box<ToolResolver>) -> Build {
+        Build::create(config, Some(resolver))
+    }
+
+    fn create(config: Config, resolver: Option<Box<ToolResolver>>) -> Build {
+        let mut build = Build::bare(config);
+
+        build.verbose("finding compilers");
+        cc_detect::find(&mut build);
+        build.verbose("running sanity check");
+        match sanity::check(&mut build, resolver) {
+            Ok(sanity::Checked::Ready) => {}
+            Ok(sanity::Checked::Stop { output, code }) => {
+                print!("{}", output);
+                process::exit(code);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(e.kind.exit_code());
+            }
+        }
+
+        // If local-rust is the same major.minor as the current version, then force a
+        // local-rebuild
+        let local_version_verbose = output(
+            Command::new(&build.initial_rustc).arg("--version").arg("--verbose"));
+        let local_release = local_version_verbose
+            .lines().filter(|x| x.starts_with("release:"))
+            .next().unwrap().trim_left_matches("release:").trim();
+        let my_version = channel::CFG_RELEASE_NUM;
+        if local_release.split('.').take(2).eq(my_version.split('.').take(2)) {
+            build.verbose(&format!("auto-detected local-rebuild {}", local_release));
+            build.local_rebuild = true;
+        }
+
+        build.verbose("learning about cargo");
+        metadata::build(&mut build);
+
+        build
+    }
+
+    /// Creates the `Build` for `config` without finding compilers, checking
+    /// the environment or learning about cargo.
+    fn bare(config: Config) -> Build {
         let src = config.src.clone();
         let out = config.out.clone();
 
@@ -356,7 +413,7 @@ impl Build {
         let rls_info = channel::GitInfo::new(&config, &src.join("src/tools/rls"));
         let rustfmt_info = channel::GitInfo::new(&config, &src.join("src/tools/rustfmt"));
 
-        let mut build = Build {
+        Build {
             initial_rustc: config.initial_rustc.clone(),
             initial_cargo: config.initial_cargo.clone(),
             local_rebuild: config.local_rebuild,
@@ -382,35 +439,17 @@ impl Build {
             crates: HashMap::new(),
             lldb_version: None,
             lldb_python_dir: None,
+            lldb_python_unusable: None,
+            nodejs_version: None,
+            sccache_version: None,
+            gdb_version: None,
+            gdb_unsupported: None,
             is_sudo,
             ci_env: CiEnv::current(),
             delayed_failures: RefCell::new(Vec::new()),
             prerelease_version: Cell::new(None),
             tool_artifacts: Default::default(),
-        };
-
-        build.verbose("finding compilers");
-        cc_detect::find(&mut build);
-        build.verbose("running sanity check");
-        sanity::check(&mut build);
-
-        // If local-rust is the same major.minor as the current version, then force a
-        // local-rebuild
-        let local_version_verbose = output(
-            Command::new(&build.initial_rustc).arg("--version").arg("--verbose"));
-        let local_release = local_version_verbose
-            .lines().filter(|x| x.starts_with("release:"))
-            .next().unwrap().trim_left_matches("release:").trim();
-        let my_version = channel::CFG_RELEASE_NUM;
-        if local_release.split('.').take(2).eq(my_version.split('.').take(2)) {
-            build.verbose(&format!("auto-detected local-rebuild {}", local_release));
-            build.local_rebuild = true;
         }
-
-        build.verbose("learning about cargo");
-        metadata::build(&mut build);
-
-        build
     }
 
     pub fn build_triple(&self) -> &[Interned<String>] {
@@ -481,15 +520,24 @@ impl Build {
         cleared
     }
 
+    /// Returns whether the standard library and compiler for `target` use
+    /// jemalloc.
+    ///
+    /// This is decided per target so a mix of hosts like msvc and gnu can
+    /// share one `rust.use-jemalloc`.
+    fn use_jemalloc(&self, target: Interned<String>) -> bool {
+        self.config.use_jemalloc && sanity::jemalloc_supported(&target)
+    }
+
     /// Get the space-separated set of activated features for the standard
     /// library.
-    fn std_features(&self) -> String {
+    fn std_features(&self, target: Interned<String>) -> String {
         let mut features = "panic-unwind".to_string();
 
         if self.config.debug_jemalloc {
             features.push_str(" debug-jemalloc");
         }
-        if self.config.use_jemalloc {
+        if self.use_jemalloc(target) {
             features.push_str(" jemalloc");
         }
         if self.config.backtrace {
@@ -505,9 +553,9 @@ impl Build {
     }
 
     /// Get the space-separated set of activated features for the compiler.
-    fn rustc_features(&self) -> String {
+    fn rustc_features(&self, target: Interned<String>) -> String {
         let mut features = String::new();
-        if self.config.use_jemalloc {
+        if self.use_jemalloc(target) {
             features.push_str(" jemalloc");
         }
         features
@@ -607,7 +655,8 @@ impl Build {
             Path::new(llvm_bindir.trim()).join(exe("FileCheck", &*target))
         } else {
             let base = self.llvm_out(self.config.build).join("build");
-            let base = if !self.config.ninja && self.config.build.contains("msvc") {
+            let generator = sanity::cmake_generator(&self.config, &self.config.build);
+            let base = if generator == sanity::CmakeGenerator::VisualStudio {
                 if self.config.llvm_optimize {
                     if self.config.llvm_release_debuginfo {
                         base.join("RelWithDebInfo")
@@ -33,6 +33,7 @@ use util::{self, exe};
 use build_helper::up_to_date;
 use builder::{Builder, RunConfig, ShouldRun, Step};
 use cache::Interned;
+use sanity::{self, CmakeGenerator};
 
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
 pub struct Llvm {
@@ -83,7 +84,8 @@ impl Step for Llvm {
             (dir, config_dir)
         } else {
             let mut dir = builder.llvm_out(builder.config.build);
-            if !builder.config.build.contains("msvc") || builder.config.ninja {
+            let generator = sanity::cmake_generator(&builder.config, &builder.config.build);
+            if generator != CmakeGenerator::VisualStudio {
                 dir.push("build");
             }
             (builder.llvm_out(target), dir.join("bin"))
@@ -254,7 +256,8 @@ fn configure_cmake(builder: &Builder,
                    target: Interned<String>,
                    cfg: &mut cmake::Config,
                    building_dist_binaries: bool) {
-    if builder.config.ninja {
+    let generator = sanity::cmake_generator(&builder.config, &target);
+    if generator == CmakeGenerator::Ninja {
         cfg.generator("Ninja");
     }
     cfg.target(&target)
@@ -271,7 +274,7 @@ fn configure_cmake(builder: &Builder,
     // MSVC with CMake uses msbuild by default which doesn't respect these
     // vars that we'd otherwise configure. In that case we just skip this
     // entirely.
-    if target.contains("msvc") && !builder.config.ninja {
+    if generator == CmakeGenerator::VisualStudio {
         return
     }
 
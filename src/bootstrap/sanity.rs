@@ -18,248 +18,7562 @@
 //! In theory if we get past this phase it's a bug if a build fails, but in
 //! practice that's likely not true!
 
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::{BTreeMap, HashMap};
+use std::collections::hash_map::DefaultHasher;
 use std::env;
+use std::error::Error;
 use std::ffi::{OsString, OsStr};
+use std::fmt;
 use std::fs::{self, File};
-use std::io::Read;
-use std::path::PathBuf;
-use std::process::Command;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
-use build_helper::output;
+#[cfg(unix)]
+use libc;
+use num_cpus;
+use serde_json;
 
 use Build;
+use cache::Interned;
+use channel;
+use config::Config;
+use flags::Subcommand;
+use util::{exe, CiEnv};
+
+/// Resolves the names of tools to the paths `check` should use for them.
+///
+/// The default, `PathResolver`, searches `PATH`, but embedders which already
+/// know where every tool lives can plug in their own through
+/// `Build::with_tool_resolver`.
+pub trait ToolResolver {
+    /// Returns the path to use for the tool `name`, if there is one.
+    fn resolve(&mut self, name: &OsStr) -> Option<PathBuf>;
+
+    /// Returns why `name` couldn't be resolved, if there's more to say than
+    /// that it wasn't found.
+    fn explain_missing(&self, _name: &OsStr) -> Option<String> {
+        None
+    }
+}
+
+/// The parts of the filesystem `PathResolver` looks at, so tests can give it
+/// a virtual `PATH` to search.
+pub trait PathFs {
+    fn is_file(&self, path: &Path) -> bool;
+    fn exists(&self, path: &Path) -> bool;
+    /// Returns what `path` points to if it's a symlink to something which
+    /// doesn't exist.
+    fn broken_symlink(&self, path: &Path) -> Option<PathBuf>;
+}
+
+/// The real filesystem.
+pub struct RealFs;
+
+impl PathFs for RealFs {
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn broken_symlink(&self, path: &Path) -> Option<PathBuf> {
+        broken_symlink(path)
+    }
+}
+
+/// Searches `PATH`, and any directories prepended to it, for tools.
+pub struct PathResolver<F: PathFs = RealFs> {
+    fs: F,
+    path: OsString,
+    // Directories searched before `path`, along with where they came from.
+    extra: Vec<(PathBuf, &'static str)>,
+    // Dangling symlinks with the name of a command we couldn't find, and
+    // what they point to.
+    broken: HashMap<OsString, (PathBuf, PathBuf)>,
+    // Whether to skip relative entries of `path`, see `build.strict-path`.
+    skip_relative: bool,
+    verbose: bool,
+}
+
+impl PathResolver {
+    pub fn new() -> Self {
+        PathResolver::with_path(env::var_os("PATH").unwrap_or_default())
+    }
+
+    /// Creates a resolver which searches `path` instead of `$PATH`.
+    fn with_path(path: OsString) -> Self {
+        PathResolver::with_fs(RealFs, path)
+    }
+}
+
+impl<F: PathFs> PathResolver<F> {
+    /// Creates a resolver which searches `path` in `fs`.
+    fn with_fs(fs: F, path: OsString) -> Self {
+        PathResolver {
+            fs,
+            path,
+            extra: Vec::new(),
+            broken: HashMap::new(),
+            skip_relative: false,
+            verbose: false,
+        }
+    }
+
+    /// Searches `dir` before any other directory, describing tools found
+    /// there as coming from `source` in verbose output.
+    fn prepend(&mut self, dir: PathBuf, source: &'static str) {
+        self.extra.insert(0, (dir, source));
+    }
+}
+
+impl<F: PathFs> ToolResolver for PathResolver<F> {
+    fn resolve(&mut self, cmd: &OsStr) -> Option<PathBuf> {
+        let skip_relative = self.skip_relative;
+        let dirs = self.extra.clone().into_iter()
+            .chain(env::split_paths(&self.path).map(|dir| (dir, "PATH")))
+            .filter(|&(ref dir, _)| !(skip_relative && is_relative_entry(dir)));
+        for (dir, source) in dirs {
+            let target = dir.join(cmd);
+            let mut cmd_alt = cmd.to_os_string();
+            cmd_alt.push(".exe");
+            if self.fs.is_file(&target) || // some/path/git
+            self.fs.exists(&target.with_extension("exe")) || // some/path/git.exe
+            self.fs.exists(&target.join(&cmd_alt)) { // some/path/git/git.exe
+                if self.verbose {
+                    println!("sanity: using {} from {}", target.display(), source);
+                }
+                return Some(target);
+            }
+            if !self.broken.contains_key(cmd) {
+                if let Some(link) = self.fs.broken_symlink(&target) {
+                    self.broken.insert(cmd.to_os_string(), (target.clone(), link));
+                }
+            }
+        }
+        None
+    }
+
+    fn explain_missing(&self, cmd: &OsStr) -> Option<String> {
+        self.broken.get(cmd).map(|&(ref path, ref link)| {
+            format!("found a broken symlink at {} pointing to {}", path.display(), link.display())
+        })
+    }
+}
 
 struct Finder {
     cache: HashMap<OsString, Option<PathBuf>>,
-    path: OsString,
+    resolver: Box<ToolResolver>,
+    // Whether someone's likely running this from their shell, rather than
+    // on CI.
+    interactive: bool,
+    // The commands `require` couldn't find, in the order it was asked for
+    // them.
+    missing: Vec<OsString>,
+    // The build triple, which decides how missing commands are suggested to
+    // be installed.
+    build: Option<Interned<String>>,
+    // The versions the checks probed of the tools they found, by path.
+    versions: HashMap<PathBuf, String>,
 }
 
 impl Finder {
-    fn new() -> Self {
+    /// Creates a finder which searches `path` instead of `$PATH`.
+    fn with_path(path: OsString) -> Self {
+        Finder::with_resolver(Box::new(PathResolver::with_path(path)))
+    }
+
+    fn with_resolver(resolver: Box<ToolResolver>) -> Self {
         Self {
             cache: HashMap::new(),
-            path: env::var_os("PATH").unwrap_or_default()
+            resolver,
+            interactive: false,
+            missing: Vec::new(),
+            build: None,
+            versions: HashMap::new(),
         }
     }
 
     fn maybe_have<S: AsRef<OsStr>>(&mut self, cmd: S) -> Option<PathBuf> {
         let cmd: OsString = cmd.as_ref().into();
-        let path = self.path.clone();
-        self.cache.entry(cmd.clone()).or_insert_with(|| {
-            for path in env::split_paths(&path) {
-                let target = path.join(&cmd);
-                let mut cmd_alt = cmd.clone();
-                cmd_alt.push(".exe");
-                if target.is_file() || // some/path/git
-                target.with_extension("exe").exists() || // some/path/git.exe
-                target.join(&cmd_alt).exists() { // some/path/git/git.exe
-                    return Some(target);
+        if let Some(found) = self.cache.get(&cmd) {
+            return found.clone()
+        }
+        let found = self.resolver.resolve(&cmd);
+        self.cache.insert(cmd, found.clone());
+        found
+    }
+
+    /// Returns the first of `names` which can be found, trying them in order.
+    fn first_of(&mut self, names: &[&str]) -> Option<PathBuf> {
+        names.iter().filter_map(|name| self.maybe_have(name)).next()
+    }
+
+    /// Fills the cache with what `save_cache` wrote to `file`, if it was
+    /// written with the same `key` and what it found still exists. Returns
+    /// how many commands were loaded.
+    fn load_cache(&mut self, file: &Path, key: &str) -> usize {
+        let mut contents = String::new();
+        if File::open(file).and_then(|mut f| f.read_to_string(&mut contents)).is_err() {
+            return 0
+        }
+        let mut lines = contents.lines();
+        if lines.next() != Some(key) {
+            return 0
+        }
+        let mut loaded = 0;
+        for line in lines {
+            let mut parts = line.splitn(2, '\t');
+            let (cmd, path) = match (parts.next(), parts.next()) {
+                (Some(cmd), Some(path)) => (cmd, PathBuf::from(path)),
+                _ => continue,
+            };
+            if path.exists() || path.with_extension("exe").exists() {
+                self.cache.insert(cmd.into(), Some(path));
+                loaded += 1;
+            }
+        }
+        loaded
+    }
+
+    /// Writes the commands which were found to `file`, for `load_cache` to
+    /// read back as long as `key` is the same. Misses aren't written, so a
+    /// tool installed since is always found.
+    fn save_cache(&self, file: &Path, key: &str) -> io::Result<()> {
+        let mut contents = format!("{}\n", key);
+        let mut found = self.cache.iter()
+            .filter_map(|(cmd, path)| Some((cmd.to_str()?, path.as_ref()?.to_str()?)))
+            .filter(|&(cmd, path)| !(cmd.contains('\t') || cmd.contains('\n') ||
+                                     path.contains('\n')))
+            .collect::<Vec<_>>();
+        found.sort();
+        for (cmd, path) in found {
+            contents.push_str(&format!("{}\t{}\n", cmd, path));
+        }
+        File::create(file)?.write_all(contents.as_bytes())
+    }
+
+    fn must_have<S: AsRef<OsStr>>(&mut self, cmd: S) -> Result<PathBuf, SanityError> {
+        match self.maybe_have(&cmd) {
+            Some(path) => executable(cmd.as_ref(), path),
+            None => Err(self.missing_error(cmd.as_ref())),
+        }
+    }
+
+    /// Like `must_have`, but for a tool which goes by any of `names`, which
+    /// are tried in order. Whichever is found is cached under the first,
+    /// canonical name too, so later lookups of that don't search again.
+    fn must_have_one_of(&mut self, names: &[&str]) -> Result<PathBuf, SanityError> {
+        let canonical = names.first().expect("must_have_one_of needs a name");
+        if let Some(path) = self.first_of(names) {
+            self.cache.insert(canonical.into(), Some(path.clone()));
+            return executable(OsStr::new(canonical), path)
+        }
+        if names.len() == 1 {
+            return Err(self.missing_error(OsStr::new(canonical)))
+        }
+        let names = names.iter().map(|name| format!("{:?}", name)).collect::<Vec<_>>();
+        let mut msg = format!("\n\ncouldn't find required command {}, under any of the names \
+                               {}\n\n", names[0], names.join(", "));
+        if let Some(hint) = self.install_hint(OsStr::new(canonical)) {
+            msg.push_str(&format!("{}\n\n", hint));
+        }
+        Err(SanityError::new(ErrorKind::MissingTool, msg).about(canonical))
+    }
+
+    /// Like `must_have`, but rather than failing if `cmd` can't be found this
+    /// records it for `finish`, so every missing command is reported at once.
+    fn require<S: AsRef<OsStr>>(&mut self, cmd: S) -> Result<Option<PathBuf>, SanityError> {
+        match self.maybe_have(&cmd) {
+            Some(path) => executable(cmd.as_ref(), path).map(Some),
+            None => {
+                if !self.missing.iter().any(|missing| missing == cmd.as_ref()) {
+                    self.missing.push(cmd.as_ref().to_os_string());
                 }
+                Ok(None)
             }
-            None
-        }).clone()
+        }
     }
 
-    fn must_have<S: AsRef<OsStr>>(&mut self, cmd: S) -> PathBuf {
-        self.maybe_have(&cmd).unwrap_or_else(|| {
-            panic!("\n\ncouldn't find required command: {:?}\n\n", cmd.as_ref());
-        })
+    /// Fails with every command `require` couldn't find, if there were any.
+    fn finish(&self) -> Result<(), SanityError> {
+        match self.missing.len() {
+            0 => Ok(()),
+            1 => Err(self.missing_error(&self.missing[0])),
+            n => {
+                let mut msg = format!("\n\ncouldn't find {} required commands:\n\n", n);
+                let mut hints = Vec::new();
+                for cmd in &self.missing {
+                    match self.explain_missing(cmd) {
+                        Some(why) => msg.push_str(&format!("    {:?}: {}\n", cmd, why)),
+                        None => {
+                            msg.push_str(&format!("    {:?}\n", cmd));
+                            hints.extend(self.install_hint(cmd));
+                        }
+                    }
+                }
+                if !hints.is_empty() {
+                    msg.push_str(&format!("\n{}\n", hints.join("\n")));
+                }
+                if self.interactive {
+                    msg.push_str(&format!("\n{}\n", alias_advice()));
+                }
+                msg.push('\n');
+                Err(SanityError::new(ErrorKind::MissingTool, msg))
+            }
+        }
+    }
+
+    /// Returns why `cmd` can't be found, if there's more to say than that
+    /// it isn't in `PATH`.
+    fn explain_missing(&self, cmd: &OsStr) -> Option<String> {
+        self.resolver.explain_missing(cmd)
+            .or_else(|| shell_alias_hint(cmd, &|name| env::var_os(name)))
+    }
+
+    fn missing_error(&self, cmd: &OsStr) -> SanityError {
+        if let Some(why) = self.explain_missing(cmd) {
+            return SanityError::new(ErrorKind::MissingTool,
+                                    format!("\n\ncouldn't find required command {:?}: {}\n\n",
+                                            cmd, why))
+                .about(cmd)
+        }
+        let mut msg = format!("\n\ncouldn't find required command: {:?}\n\n", cmd);
+        if let Some(hint) = self.install_hint(cmd) {
+            msg.push_str(&format!("{}\n\n", hint));
+        }
+        if self.interactive {
+            msg.push_str(&format!("{}\n\n", alias_advice()));
+        }
+        SanityError::new(ErrorKind::MissingTool, msg).about(cmd)
+    }
+
+    fn install_hint(&self, cmd: &OsStr) -> Option<String> {
+        let name = Path::new(cmd).file_name()?.to_str()?;
+        install_hint(name, self.build.as_ref().map_or("", |build| &build[..]))
+    }
+
+    /// Like `must_have`, but for a tool which is only required if `cmd` has
+    /// been configured.
+    fn must_have_if_set<S: AsRef<OsStr>>(&mut self, cmd: Option<S>)
+                                         -> Result<Option<PathBuf>, SanityError> {
+        match cmd {
+            Some(cmd) => self.must_have(cmd).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Returns `path`, where `cmd` was found, if it can be run.
+fn executable(cmd: &OsStr, path: PathBuf) -> Result<PathBuf, SanityError> {
+    match executable_problem(&path) {
+        Some(problem) => {
+            Err(SanityError::new(ErrorKind::Permission,
+                                 format!("\n\nfound required command {:?} at {}, but it \
+                                          {}\n\n",
+                                         cmd, path.display(), problem))
+                .about(cmd))
+        }
+        None => Ok(path),
     }
 }
 
-pub fn check(build: &mut Build) {
+/// Where in the build directory `check` keeps the commands it found, so the
+/// next invocation doesn't have to search `PATH` for them again.
+const TOOL_CACHE_FILE: &str = "sanity-tool-cache";
+
+/// The environment variable which, when set, makes `check` search for every
+/// command again rather than using or updating `TOOL_CACHE_FILE`.
+const NO_TOOL_CACHE_VAR: &str = "RUSTBUILD_NO_TOOL_CACHE";
+
+/// Returns what `TOOL_CACHE_FILE` is only valid for: the directories which
+/// are searched, and when any of them last had a file added or removed.
+fn tool_cache_key(config: &Config) -> String {
     let path = env::var_os("PATH").unwrap_or_default();
-    // On Windows, quotes are invalid characters for filename paths, and if
-    // one is present as part of the PATH then that can lead to the system
-    // being unable to identify the files properly. See
-    // https://github.com/rust-lang/rust/issues/34959 for more details.
-    if cfg!(windows) && path.to_string_lossy().contains("\"") {
-        panic!("PATH contains invalid character '\"'");
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    config.tools_bin.hash(&mut hasher);
+    config.strict_path.hash(&mut hasher);
+    for dir in config.tools_bin.iter().cloned().chain(env::split_paths(&path)) {
+        fs::metadata(&dir).and_then(|meta| meta.modified()).ok().hash(&mut hasher);
+    }
+    format!("{:016x}", hasher.finish())
+}
+
+/// The environment variable users can put the output of `alias` in, so a
+/// missing command that's actually a shell alias can be pointed out.
+const SHELL_ALIASES_VAR: &str = "RUSTBUILD_SHELL_ALIASES";
+
+/// The packages which provide the commands the build commonly needs, by
+/// command: `(command, apt, dnf, pacman, brew)`. `None` means that package
+/// manager doesn't have it, or the command comes with the OS there.
+static PACKAGES: &[(&str, Option<&str>, Option<&str>, Option<&str>, Option<&str>)] = &[
+    ("git", Some("git"), Some("git"), Some("git"), Some("git")),
+    ("cmake", Some("cmake"), Some("cmake"), Some("cmake"), Some("cmake")),
+    ("ninja", Some("ninja-build"), Some("ninja-build"), Some("ninja"), Some("ninja")),
+    ("ninja-build", Some("ninja-build"), Some("ninja-build"), None, None),
+    ("make", Some("make"), Some("make"), Some("make"), None),
+    ("curl", Some("curl"), Some("curl"), Some("curl"), None),
+    ("cc", Some("gcc"), Some("gcc"), Some("gcc"), None),
+    ("gcc", Some("gcc"), Some("gcc"), Some("gcc"), Some("gcc")),
+    ("c++", Some("g++"), Some("gcc-c++"), Some("gcc"), None),
+    ("g++", Some("g++"), Some("gcc-c++"), Some("gcc"), Some("gcc")),
+    ("clang", Some("clang"), Some("clang"), Some("clang"), None),
+    ("ccache", Some("ccache"), Some("ccache"), Some("ccache"), Some("ccache")),
+    ("pkg-config", Some("pkg-config"), Some("pkgconf-pkg-config"), Some("pkgconf"),
+     Some("pkg-config")),
+    ("python", Some("python"), Some("python2"), Some("python2"), None),
+    ("python2.7", Some("python2.7"), Some("python2"), Some("python2"), None),
+    ("gdb", Some("gdb"), Some("gdb"), Some("gdb"), Some("gdb")),
+    ("tar", Some("tar"), Some("tar"), Some("tar"), None),
+    ("gzip", Some("gzip"), Some("gzip"), Some("gzip"), None),
+];
+
+/// Suggests how to install the command `name` on the build triple `build`,
+/// if it's one of `PACKAGES` and the triple has a package manager we know.
+fn install_hint(name: &str, build: &str) -> Option<String> {
+    let name = name.trim_right_matches(".exe");
+    let &(_, apt, dnf, pacman, brew) = PACKAGES.iter().find(|p| p.0 == name)?;
+    let ways = if build.contains("linux") {
+        vec![apt.map(|p| format!("`apt install {}` on Debian and Ubuntu", p)),
+             dnf.map(|p| format!("`dnf install {}` on Fedora", p)),
+             pacman.map(|p| format!("`pacman -S {}` on Arch", p))]
+    } else if build.contains("apple-darwin") {
+        vec![brew.map(|p| format!("`brew install {}` with Homebrew", p))]
+    } else {
+        return None
+    };
+    let ways = ways.into_iter().filter_map(|way| way).collect::<Vec<_>>();
+    let (last, rest) = ways.split_last()?;
+    if rest.is_empty() {
+        Some(format!("{} can be installed with {}.", name, last))
+    } else {
+        Some(format!("{} can be installed with {} or {}.", name, rest.join(", "), last))
     }
+}
 
-    let mut cmd_finder = Finder::new();
-    // If we've got a git directory we're gonna need git to update
-    // submodules and learn about various other aspects.
-    if build.rust_info.is_git() {
-        cmd_finder.must_have("git");
+/// What to tell someone running the build from their shell when a command
+/// is missing, as it may be an alias there.
+fn alias_advice() -> String {
+    format!("If it works in your shell it may be a shell alias or function there, but only \
+             programs in PATH can be run by the build. Running with {}=\"$(alias)\" lets \
+             the build tell.", SHELL_ALIASES_VAR)
+}
+
+/// Explains that `cmd` is a shell function or alias rather than a program,
+/// going by what the environment (looked up with `var`) gives away.
+fn shell_alias_hint(cmd: &OsStr, var: &Fn(&str) -> Option<OsString>) -> Option<String> {
+    let cmd = cmd.to_str()?;
+    let not_a_program = "but only programs in PATH can be run by the build";
+
+    // Functions exported with `export -f` end up in the environment of
+    // everything bash runs, under one of these names depending on its version.
+    let exported = [format!("BASH_FUNC_{}%%", cmd), format!("BASH_FUNC_{}()", cmd)];
+    if exported.iter().any(|name| var(name).is_some()) {
+        return Some(format!("{} is an exported shell function, {}", cmd, not_a_program))
     }
 
-    // We need cmake, but only if we're actually building LLVM or sanitizers.
-    let building_llvm = build.hosts.iter()
-        .filter_map(|host| build.config.target_config.get(host))
-        .any(|config| config.llvm_config.is_none());
-    if building_llvm || build.config.sanitizers {
-        cmd_finder.must_have("cmake");
+    let aliases = var(SHELL_ALIASES_VAR)?;
+    let prefix = format!("{}=", cmd);
+    let aliased = aliases.to_string_lossy().lines().any(|line| {
+        line.trim().trim_left_matches("alias ").starts_with(&prefix)
+    });
+    if aliased {
+        Some(format!("{} is a shell alias according to ${}, {}",
+                     cmd, SHELL_ALIASES_VAR, not_a_program))
+    } else {
+        None
     }
+}
 
-    // Ninja is currently only used for LLVM itself.
-    if building_llvm {
-        if build.config.ninja {
-            // Some Linux distros rename `ninja` to `ninja-build`.
-            // CMake can work with either binary name.
-            if cmd_finder.maybe_have("ninja-build").is_none() {
-                cmd_finder.must_have("ninja");
+/// Returns whether the `PATH` entry `dir` depends on the working directory,
+/// which includes the empty entry.
+fn is_relative_entry(dir: &Path) -> bool {
+    dir.as_os_str().is_empty() || dir.is_relative()
+}
+
+/// Returns the entries of `path` which depend on the working directory.
+fn relative_path_entries(path: &OsStr) -> Vec<PathBuf> {
+    env::split_paths(path).filter(|dir| is_relative_entry(dir)).collect()
+}
+
+/// Returns what `path` points to if it's a symlink to something which
+/// doesn't exist.
+fn broken_symlink(path: &Path) -> Option<PathBuf> {
+    let meta = fs::symlink_metadata(path).ok()?;
+    if meta.file_type().is_symlink() && fs::metadata(path).is_err() {
+        fs::read_link(path).ok()
+    } else {
+        None
+    }
+}
+
+/// Returns why the file `maybe_have` found at `path` can't be executed by
+/// us, if it can't.
+fn executable_problem(path: &Path) -> Option<String> {
+    // `maybe_have` also accepts `path.exe`, and for `path/name.exe` there's
+    // no file to look at in the first place.
+    let file = if path.is_file() {
+        path.to_path_buf()
+    } else if path.with_extension("exe").is_file() {
+        path.with_extension("exe")
+    } else {
+        return None
+    };
+    executable_file_problem(&file)
+}
+
+#[cfg(unix)]
+fn executable_file_problem(file: &Path) -> Option<String> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(file).ok()?.permissions().mode();
+    if mode & 0o111 == 0 {
+        return Some(format!("isn't executable (its mode is {:o})", mode & 0o7777))
+    }
+    let path = CString::new(file.as_os_str().as_bytes()).ok()?;
+    if unsafe { libc::access(path.as_ptr(), libc::X_OK) } != 0 {
+        return Some(format!("isn't executable by the current user (its mode is {:o}, \
+                             and it might be on a `noexec` mount): {}",
+                            mode & 0o7777, io::Error::last_os_error()))
+    }
+    None
+}
+
+#[cfg(windows)]
+fn executable_file_problem(file: &Path) -> Option<String> {
+    // Windows decides what it can run by extension alone.
+    let pathext = env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+    let ext = match file.extension() {
+        Some(ext) => format!(".{}", ext.to_string_lossy()),
+        None => return Some("has no file extension, so Windows can't run it".to_string()),
+    };
+    if pathext.split(';').any(|known| known.eq_ignore_ascii_case(&ext)) {
+        None
+    } else {
+        Some(format!("has the extension {}, which isn't a type Windows can run (PATHEXT is {})",
+                     ext, pathext))
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn executable_file_problem(_file: &Path) -> Option<String> {
+    None
+}
+
+/// A change to `build.config`, or to what the build does with the tools it
+/// found, which auto-detection has decided to make.
+///
+/// Rather than writing to the configuration as soon as something is detected
+/// these are collected during `check` and applied at the very end, which lets
+/// `--sanity-diff` print them instead.
+enum Mutation {
+    Python(PathBuf),
+    Nodejs(Option<PathBuf>),
+    Gdb(Option<PathBuf>),
+    Ninja(bool),
+    NoStd(Interned<String>),
+    MuslRoot(Interned<String>, PathBuf),
+    Sdk(Interned<String>, PathBuf),
+    /// The paths of the SDK the target needs, once they're all there.
+    SdkPaths(Interned<String>, Vec<PathBuf>),
+    /// Doesn't use the directory `lldb -P` printed, for the reason given.
+    NoLldbPythonDir(String),
+    /// Skips the debuginfo-gdb tests, for the reason given.
+    GdbUnsupported(String),
+}
+
+impl Mutation {
+    /// The `config.toml` key this mutation corresponds to, or what it
+    /// changes if it isn't one.
+    fn key(&self) -> String {
+        match *self {
+            Mutation::Python(..) => "build.python".to_string(),
+            Mutation::Nodejs(..) => "build.nodejs".to_string(),
+            Mutation::Gdb(..) => "build.gdb".to_string(),
+            Mutation::Ninja(..) => "llvm.ninja".to_string(),
+            Mutation::NoStd(target) => format!("target.{}.no-std", target),
+            Mutation::MuslRoot(target, _) => format!("target.{}.musl-root", target),
+            Mutation::Sdk(target, _) => format!("target.{}.sdk", target),
+            Mutation::SdkPaths(target, _) => format!("the SDK paths of {}", target),
+            Mutation::NoLldbPythonDir(..) => "lldb's Python directory".to_string(),
+            Mutation::GdbUnsupported(..) => "the debuginfo-gdb tests".to_string(),
+        }
+    }
+
+    fn before(&self, build: &Build) -> String {
+        let config = &build.config;
+        match *self {
+            Mutation::Python(..) => path_or_unset(config.python.as_ref()),
+            Mutation::Nodejs(..) => path_or_unset(config.nodejs.as_ref()),
+            Mutation::Gdb(..) => path_or_unset(config.gdb.as_ref()),
+            Mutation::Ninja(..) => config.ninja.to_string(),
+            Mutation::NoStd(target) => {
+                config.target_config.get(&target).map_or(false, |t| t.no_std).to_string()
+            }
+            Mutation::MuslRoot(target, _) => {
+                path_or_unset(config.target_config.get(&target)
+                                    .and_then(|t| t.musl_root.as_ref()))
+            }
+            Mutation::Sdk(target, _) => {
+                path_or_unset(config.target_config.get(&target).and_then(|t| t.sdk.as_ref()))
+            }
+            Mutation::SdkPaths(target, _) => {
+                format!("{:?}", config.target_config.get(&target).map_or(&[][..], |t| {
+                    &t.sdk_paths[..]
+                }))
             }
+            Mutation::NoLldbPythonDir(..) => {
+                build.lldb_python_dir.clone().unwrap_or_else(|| "(unset)".to_string())
+            }
+            Mutation::GdbUnsupported(..) => {
+                build.gdb_unsupported.as_ref().map_or("run".to_string(), |why| {
+                    format!("skipped: {}", why)
+                })
+            }
+        }
+    }
+
+    fn after(&self) -> String {
+        match *self {
+            Mutation::Python(ref p) => path_or_unset(Some(p)),
+            Mutation::Nodejs(ref p) |
+            Mutation::Gdb(ref p) => path_or_unset(p.as_ref()),
+            Mutation::Ninja(b) => b.to_string(),
+            Mutation::NoStd(..) => true.to_string(),
+            Mutation::MuslRoot(_, ref p) |
+            Mutation::Sdk(_, ref p) => path_or_unset(Some(p)),
+            Mutation::SdkPaths(_, ref paths) => format!("{:?}", paths),
+            Mutation::NoLldbPythonDir(..) => "(unset)".to_string(),
+            Mutation::GdbUnsupported(ref why) => format!("skipped: {}", why),
         }
+    }
 
-        // If ninja isn't enabled but we're building for MSVC then we try
-        // doubly hard to enable it. It was realized in #43767 that the msbuild
-        // CMake generator for MSVC doesn't respect configuration options like
-        // disabling LLVM assertions, which can often be quite important!
-        //
-        // In these cases we automatically enable Ninja if we find it in the
-        // environment.
-        if !build.config.ninja && build.config.build.contains("msvc") {
-            if cmd_finder.maybe_have("ninja").is_some() {
-                build.config.ninja = true;
+    fn apply(self, build: &mut Build) {
+        match self {
+            Mutation::Python(p) => build.config.python = Some(p),
+            Mutation::Nodejs(p) => build.config.nodejs = p,
+            Mutation::Gdb(p) => build.config.gdb = p,
+            Mutation::Ninja(b) => build.config.ninja = b,
+            Mutation::NoStd(target) => {
+                build.config.target_config.entry(target).or_insert(Default::default())
+                    .no_std = true;
+            }
+            Mutation::MuslRoot(target, p) => {
+                build.config.target_config.entry(target).or_insert(Default::default())
+                    .musl_root = Some(p);
+            }
+            Mutation::Sdk(target, p) => {
+                build.config.target_config.entry(target).or_insert(Default::default())
+                    .sdk = Some(p);
             }
+            Mutation::SdkPaths(target, paths) => {
+                build.config.target_config.entry(target).or_insert(Default::default())
+                    .sdk_paths = paths;
+            }
+            Mutation::NoLldbPythonDir(why) => {
+                build.lldb_python_dir = None;
+                build.lldb_python_unusable = Some(why);
+            }
+            Mutation::GdbUnsupported(why) => build.gdb_unsupported = Some(why),
         }
     }
+}
 
-    build.config.python = build.config.python.take().map(|p| cmd_finder.must_have(p))
-        .or_else(|| env::var_os("BOOTSTRAP_PYTHON").map(PathBuf::from)) // set by bootstrap.py
-        .or_else(|| cmd_finder.maybe_have("python2.7"))
-        .or_else(|| cmd_finder.maybe_have("python2"))
-        .or_else(|| Some(cmd_finder.must_have("python")));
+fn path_or_unset(path: Option<&PathBuf>) -> String {
+    match path {
+        Some(p) => format!("{:?}", p),
+        None => "(unset)".to_string(),
+    }
+}
 
-    build.config.nodejs = build.config.nodejs.take().map(|p| cmd_finder.must_have(p))
-        .or_else(|| cmd_finder.maybe_have("node"))
-        .or_else(|| cmd_finder.maybe_have("nodejs"));
+/// The list of mutations collected by a single run of `check`, along with the
+/// value each one replaces.
+struct Mutations {
+    list: Vec<(Mutation, String)>,
+}
 
-    build.config.gdb = build.config.gdb.take().map(|p| cmd_finder.must_have(p))
-        .or_else(|| cmd_finder.maybe_have("gdb"));
+impl Mutations {
+    /// Records `m`, unless it wouldn't actually change anything in `build`.
+    fn push(&mut self, build: &Build, m: Mutation) {
+        let before = m.before(build);
+        if before != m.after() {
+            self.list.push((m, before));
+        }
+    }
 
-    // We're gonna build some custom C code here and there, host triples
-    // also build some C++ shims for LLVM so we need a C++ compiler.
-    for target in &build.targets {
-        // On emscripten we don't actually need the C compiler to just
-        // build the target artifacts, only for testing. For the sake
-        // of easier bot configuration, just skip detection.
-        if target.contains("emscripten") {
-            continue;
+    fn apply(self, build: &mut Build) {
+        for (m, _) in self.list {
+            build.verbose(&format!("sanity: setting {} = {}", m.key(), m.after()));
+            m.apply(build);
         }
+    }
+}
 
-        if !build.config.dry_run {
-            cmd_finder.must_have(build.cc(*target));
-            if let Some(ar) = build.ar(*target) {
-                cmd_finder.must_have(ar);
-            }
+impl fmt::Display for Mutations {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.list.is_empty() {
+            return writeln!(f, "sanity check would not change any configuration");
         }
+        writeln!(f, "sanity check would make the following configuration changes:")?;
+        for &(ref m, ref before) in &self.list {
+            writeln!(f, "    {}: {} -> {}", m.key(), before, m.after())?;
+        }
+        Ok(())
     }
+}
 
-    for host in &build.hosts {
-        if !build.config.dry_run {
-            cmd_finder.must_have(build.cxx(*host).unwrap());
+/// Why `try_check` failed.
+#[derive(Debug)]
+pub struct SanityError {
+    pub kind: ErrorKind,
+    /// The name of the check which failed, or empty if `check` didn't get as
+    /// far as running any, e.g. because `config.toml` is invalid.
+    pub check: &'static str,
+    /// The tool or target the failure is about, if it's about one.
+    pub subject: Option<String>,
+    /// What's wrong, which is exactly what's printed when the build stops.
+    pub message: String,
+}
+
+impl SanityError {
+    fn new(kind: ErrorKind, message: String) -> SanityError {
+        SanityError { kind, check: "", subject: None, message }
+    }
+
+    /// Records that this error is about `subject`, a tool or a target.
+    fn about<S: AsRef<OsStr>>(mut self, subject: S) -> SanityError {
+        self.subject = Some(subject.as_ref().to_string_lossy().into_owned());
+        self
+    }
+}
+
+impl fmt::Display for SanityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for SanityError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+/// The broad kind of problem a `SanityError` is, which decides what
+/// `--sanity-only` exits with.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ErrorKind {
+    /// A required tool can't be found, or can't be run.
+    MissingTool,
+    /// A tool or SDK was found, but is too old or otherwise the wrong version.
+    BadVersion,
+    /// `config.toml` or the command line is invalid or contradicts itself.
+    Config,
+    /// Something we need to write to isn't writable.
+    Permission,
+    /// Anything else about the environment, like a missing musl-root.
+    Environment,
+    /// Warnings were promoted to errors by `build.deny-sanity-warnings`.
+    DeniedWarnings,
+}
+
+impl ErrorKind {
+    /// The exit code of `--sanity-only` when failing with this kind of error.
+    ///
+    /// Scripts rely on these, so they must never change; new kinds get new
+    /// codes. Success is 0, and 1 is left for usage errors.
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            ErrorKind::MissingTool => 2,
+            ErrorKind::BadVersion => 3,
+            ErrorKind::Config => 4,
+            ErrorKind::Permission => 5,
+            ErrorKind::Environment => 6,
+            ErrorKind::DeniedWarnings => 7,
         }
+    }
 
-        // The msvc hosts don't use jemalloc, turn it off globally to
-        // avoid packaging the dummy liballoc_jemalloc on that platform.
-        if host.contains("msvc") {
-            build.config.use_jemalloc = false;
+    /// How `--sanity-json` names this kind, which is as stable as the exit
+    /// code.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ErrorKind::MissingTool => "missing-tool",
+            ErrorKind::BadVersion => "bad-version",
+            ErrorKind::Config => "config",
+            ErrorKind::Permission => "permission",
+            ErrorKind::Environment => "environment",
+            ErrorKind::DeniedWarnings => "denied-warnings",
         }
     }
+}
 
-    // Externally configured LLVM requires FileCheck to exist
-    let filecheck = build.llvm_filecheck(build.build);
-    if !filecheck.starts_with(&build.out) && !filecheck.exists() && build.config.codegen_tests {
-        panic!("FileCheck executable {:?} does not exist", filecheck);
+/// Whether a failing check stops the build, only prints a warning, or is
+/// purely informational.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        })
     }
+}
 
-    for target in &build.targets {
-        // Can't compile for iOS unless we're on macOS
-        if target.contains("apple-ios") &&
-           !build.build.contains("apple-darwin") {
-            panic!("the iOS target is only supported on macOS");
+/// Whether a check can run in a dry run.
+///
+/// A dry run should fail wherever the real one would, so as much as possible
+/// runs in it too; only running the tools themselves is left out, since
+/// compiling test programs and asking for versions is most of the time the
+/// checks take. Checks which run tools only in passing, like `git`, are
+/// static and skip that part in a dry run.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Cost {
+    /// Only looks at the configuration, the environment and the filesystem,
+    /// which includes looking tools up in `PATH`.
+    Static,
+    /// Runs the tools it checks, so it's skipped in a dry run.
+    Probe,
+}
+
+impl fmt::Display for Cost {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Cost::Static => "static",
+            Cost::Probe => "probe",
+        })
+    }
+}
+
+/// One of the checks performed by `check`.
+///
+/// Every check has a stable `name` which is how it's referred to from the
+/// command line and `config.toml`, so don't rename these lightly.
+struct Check {
+    name: &'static str,
+    description: &'static str,
+    severity: Severity,
+    cost: Cost,
+    run: fn(&mut Sanity) -> Result<(), SanityError>,
+    /// The checks which have to pass for this one to mean anything, like
+    /// the compiler being found before its version is probed. They come
+    /// earlier in `CHECKS`.
+    after: &'static [&'static str],
+}
+
+impl Check {
+    /// Returns whether this runs with `profile`, in a dry run if `dry_run`.
+    fn runs(&self, profile: Profile, dry_run: bool) -> bool {
+        profile.includes(self.name) && !(dry_run && self.cost == Cost::Probe)
+    }
+}
+
+/// State shared between the checks of a single `check` run.
+struct Sanity<'a> {
+    build: &'a mut Build,
+    finder: Finder,
+    warnings: Warnings,
+}
+
+/// Collects the warnings of the checks, promoting them to errors if the
+/// check is listed in `build.deny-sanity-warnings`.
+///
+/// They're printed by `flush`, which only prints identical ones once, so
+/// the same warning about many targets doesn't drown out the rest.
+struct Warnings {
+    deny: Vec<String>,
+    check: &'static str,
+    promoted: Vec<String>,
+    /// Whether to only count warnings and notes rather than print them.
+    quiet: bool,
+    issued: usize,
+    /// What's still to be printed, in order, with how many times each was
+    /// reported.
+    pending: Vec<(String, usize)>,
+}
+
+impl Warnings {
+    fn new(deny: Vec<String>, quiet: bool) -> Warnings {
+        Warnings {
+            deny,
+            check: "",
+            promoted: Vec::new(),
+            quiet,
+            issued: 0,
+            pending: Vec::new(),
         }
+    }
 
-        if target.contains("-none-") {
-            if build.no_std(*target).is_none() {
-                let target = build.config.target_config.entry(target.clone())
-                    .or_insert(Default::default());
+    fn warn(&mut self, msg: String) {
+        if self.deny.iter().any(|name| name == self.check) {
+            self.report(format!("error: {} (promoted from a `{}` warning by \
+                                 build.deny-sanity-warnings)", msg, self.check));
+            self.promoted.push(msg);
+        } else {
+            self.report(format!("warning: {}", msg));
+        }
+    }
 
-                target.no_std = true;
-            }
+    /// Reports something worth knowing which isn't a problem, so it's never
+    /// promoted to an error.
+    fn note(&mut self, msg: String) {
+        self.report(format!("note: {}", msg));
+    }
 
-            if build.no_std(*target) == Some(false) {
-                panic!("All the *-none-* targets are no-std targets")
-            }
+    fn report(&mut self, line: String) {
+        self.issued += 1;
+        match self.pending.iter().position(|&(ref pending, _)| *pending == line) {
+            Some(i) => self.pending[i].1 += 1,
+            None => self.pending.push((line, 1)),
         }
+    }
 
-        // Make sure musl-root is valid
-        if target.contains("musl") {
-            // If this is a native target (host is also musl) and no musl-root is given,
-            // fall back to the system toolchain in /usr before giving up
-            if build.musl_root(*target).is_none() && build.config.build == *target {
-                let target = build.config.target_config.entry(target.clone())
-                                 .or_insert(Default::default());
-                target.musl_root = Some("/usr".into());
-            }
-            match build.musl_root(*target) {
-                Some(root) => {
-                    if fs::metadata(root.join("lib/libc.a")).is_err() {
-                        panic!("couldn't find libc.a in musl dir: {}",
-                               root.join("lib").display());
-                    }
-                    if fs::metadata(root.join("lib/libunwind.a")).is_err() {
-                        panic!("couldn't find libunwind.a in musl dir: {}",
-                               root.join("lib").display());
-                    }
-                }
-                None => {
-                    panic!("when targeting MUSL either the rust.musl-root \
-                            option or the target.$TARGET.musl-root option must \
-                            be specified in config.toml")
-                }
+    /// Prints everything reported since the last flush.
+    fn flush(&mut self) {
+        for (line, count) in self.pending.drain(..) {
+            if self.quiet {
+                continue
+            }
+            if count > 1 {
+                println!("{} (reported {} times)", line, count);
+            } else {
+                println!("{}", line);
             }
         }
+    }
+}
 
-        if target.contains("msvc") {
-            // There are three builds of cmake on windows: MSVC, MinGW, and
-            // Cygwin. The Cygwin build does not have generators for Visual
-            // Studio, so detect that here and error.
-            let out = output(Command::new("cmake").arg("--help"));
-            if !out.contains("Visual Studio") {
-                panic!("
-cmake does not support Visual Studio generators.
+/// All of the checks `check` runs, in the order it runs them.
+static CHECKS: &[Check] = &[
+    Check {
+        name: "path-quotes",
+        description: "PATH doesn't contain quotes (Windows only)",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_path_quotes,
+        after: &[],
+    },
+    Check {
+        name: "path-length",
+        description: "PATH isn't long enough to be truncated for child processes (Windows only)",
+        severity: Severity::Warning,
+        cost: Cost::Static,
+        run: check_path_length,
+        after: &[],
+    },
+    Check {
+        name: "root-user",
+        description: "the build isn't run as root, unless build.allow-root is set (Unix only)",
+        severity: Severity::Warning,
+        cost: Cost::Static,
+        run: check_root_user,
+        after: &[],
+    },
+    Check {
+        name: "build-dir",
+        description: "the build directory is writable",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_build_dir,
+        after: &[],
+    },
+    Check {
+        name: "unc-paths",
+        description: "the source and build directories aren't UNC paths (Windows only)",
+        severity: Severity::Warning,
+        cost: Cost::Static,
+        run: check_unc_paths,
+        after: &[],
+    },
+    Check {
+        name: "clock-skew",
+        description: "the build directory's filesystem agrees with the system clock",
+        severity: Severity::Warning,
+        cost: Cost::Static,
+        run: check_clock_skew,
+        after: &[],
+    },
+    Check {
+        name: "relative-path",
+        description: "PATH doesn't contain entries relative to the working directory",
+        severity: Severity::Warning,
+        cost: Cost::Static,
+        run: check_relative_path,
+        after: &[],
+    },
+    Check {
+        name: "download-cache",
+        description: "the download cache directory is writable and has free space",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_download_cache,
+        after: &[],
+    },
+    Check {
+        name: "temp-dir",
+        description: "the temporary directory exists, is writable and has free space",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_temp_dir,
+        after: &[],
+    },
+    Check {
+        name: "fd-limit",
+        description: "enough files can be open at once for the number of jobs (Unix only)",
+        severity: Severity::Warning,
+        cost: Cost::Static,
+        run: check_fd_limit,
+        after: &[],
+    },
+    Check {
+        name: "jobs",
+        description: "the number of jobs isn't far beyond the CPUs and memory",
+        severity: Severity::Warning,
+        cost: Cost::Static,
+        run: check_jobs,
+        after: &[],
+    },
+    Check {
+        name: "forbidden-tools",
+        description: "none of the tools in build.forbidden-tools can be found",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_forbidden_tools,
+        after: &[],
+    },
+    Check {
+        name: "tool-checksums",
+        description: "the tools in build.tool-checksums have the SHA-256 listed there",
+        severity: Severity::Error,
+        cost: Cost::Probe,
+        run: check_tool_checksums,
+        after: &[],
+    },
+    Check {
+        name: "python-sandbox",
+        description: "python can compile and import modules (with build.sanity-probes)",
+        severity: Severity::Warning,
+        cost: Cost::Probe,
+        run: check_python_sandbox,
+        after: &[],
+    },
+    Check {
+        name: "git",
+        description: "git with submodule support is available when building from a git checkout",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_git,
+        after: &[],
+    },
+    Check {
+        name: "cmake",
+        description: "cmake 3.4.3 or later is available when building LLVM or sanitizers",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_cmake,
+        after: &[],
+    },
+    Check {
+        name: "ninja",
+        description: "ninja is available when llvm.ninja is enabled",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_ninja,
+        after: &[],
+    },
+    Check {
+        name: "pkg-config",
+        description: "pkg-config can find OpenSSL for cargo in extended builds",
+        severity: Severity::Warning,
+        cost: Cost::Static,
+        run: check_pkg_config,
+        after: &[],
+    },
+    Check {
+        name: "lldb-python",
+        description: "the Python directory lldb reports contains its `lldb` module",
+        severity: Severity::Warning,
+        cost: Cost::Static,
+        run: check_lldb_python_dir,
+        after: &[],
+    },
+    Check {
+        name: "nodejs-version",
+        description: "node.js, if found, is new enough for the rustdoc-js tests",
+        severity: Severity::Warning,
+        cost: Cost::Static,
+        run: check_nodejs_version,
+        after: &[],
+    },
+    Check {
+        name: "gdb-version",
+        description: "gdb, if found, is new enough for the debuginfo tests' pretty-printers",
+        severity: Severity::Warning,
+        cost: Cost::Static,
+        run: check_gdb_version,
+        after: &[],
+    },
+    Check {
+        name: "tool-paths",
+        description: "target.$TARGET.cc, cxx, ar and linker name a tool which exists",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_tool_paths,
+        after: &[],
+    },
+    Check {
+        name: "c-compilers",
+        description: "the C compiler and archiver of every target exist",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_c_compilers,
+        after: &[],
+    },
+    Check {
+        name: "cross-ar",
+        description: "GCC cross compilers aren't paired with the host's ar",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_cross_archivers,
+        after: &["c-compilers"],
+    },
+    Check {
+        name: "cc-target-arch",
+        description: "C compilers produce objects for their target (with build.sanity-probes)",
+        severity: Severity::Warning,
+        cost: Cost::Probe,
+        run: check_cc_target_arches,
+        after: &["c-compilers"],
+    },
+    Check {
+        name: "cc-c99",
+        description: "the C compilers accept C99 by default (with build.sanity-probes)",
+        severity: Severity::Error,
+        cost: Cost::Probe,
+        run: check_c_standard,
+        after: &["c-compilers"],
+    },
+    Check {
+        name: "pie-defaults",
+        description: "the C compilers don't default to code the linker can't make a PIE of \
+                      (with build.sanity-probes)",
+        severity: Severity::Warning,
+        cost: Cost::Probe,
+        run: check_pie_defaults,
+        after: &["c-compilers"],
+    },
+    Check {
+        name: "cc-max-version",
+        description: "C compilers aren't newer than target.$TARGET.cc-max-version",
+        severity: Severity::Warning,
+        cost: Cost::Probe,
+        run: check_cc_max_versions,
+        after: &["c-compilers"],
+    },
+    Check {
+        name: "implicit-compiler-cache",
+        description: "notes C/C++ compilers which are ccache or distcc shims",
+        severity: Severity::Note,
+        cost: Cost::Static,
+        run: check_implicit_compiler_caches,
+        after: &[],
+    },
+    Check {
+        name: "linker-object-format",
+        description: "configured linkers support the object format of their target",
+        severity: Severity::Warning,
+        cost: Cost::Probe,
+        run: check_linker_object_formats,
+        after: &[],
+    },
+    Check {
+        name: "wasm-linker",
+        description: "wasm32 targets have an LLD to link with, either rust-lld or wasm-ld",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_wasm_linkers,
+        after: &[],
+    },
+    Check {
+        name: "host-linker-speed",
+        description: "notes hosts linking with a single-threaded linker like GNU ld, with \
+                      build.sanity-probes",
+        severity: Severity::Note,
+        cost: Cost::Probe,
+        run: check_host_linker_speed,
+        after: &[],
+    },
+    Check {
+        name: "debug-link-size",
+        description: "binaries with debuginfo aren't linked by a 32-bit toolchain",
+        severity: Severity::Warning,
+        cost: Cost::Static,
+        run: check_debug_link_size,
+        after: &[],
+    },
+    Check {
+        name: "cxx-compilers",
+        description: "the C++ compiler of every host exists",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_cxx_compilers,
+        after: &[],
+    },
+    Check {
+        name: "cxx-headers",
+        description: "C++ compilers have standard headers (with build.sanity-probes)",
+        severity: Severity::Error,
+        cost: Cost::Probe,
+        run: check_cxx_headers,
+        after: &["cxx-compilers"],
+    },
+    Check {
+        name: "debuginfo-formats",
+        description: "the C and C++ compilers of a host emit the same DWARF version \
+                      (with build.sanity-probes)",
+        severity: Severity::Warning,
+        cost: Cost::Probe,
+        run: check_debuginfo_formats,
+        after: &["c-compilers", "cxx-compilers"],
+    },
+    Check {
+        name: "llvm-cmake-cache",
+        description: "an existing LLVM build matches the current sources and C compiler",
+        severity: Severity::Warning,
+        cost: Cost::Static,
+        run: check_llvm_cmake_caches,
+        after: &[],
+    },
+    Check {
+        name: "static-host-libs",
+        description: "statically linked hosts have static C++ runtime libraries",
+        severity: Severity::Error,
+        cost: Cost::Probe,
+        run: check_static_hosts,
+        after: &["cxx-compilers"],
+    },
+    Check {
+        name: "proc-macro-hosts",
+        description: "host compilers are linked so that they can load proc-macros",
+        severity: Severity::Warning,
+        cost: Cost::Static,
+        run: check_proc_macro_hosts,
+        after: &[],
+    },
+    Check {
+        name: "crt-static",
+        description: "target.$TARGET.crt-static isn't set to something the target can't do",
+        severity: Severity::Warning,
+        cost: Cost::Static,
+        run: check_crt_static,
+        after: &[],
+    },
+    Check {
+        name: "sanitizer-runtimes",
+        description: "sanitizer runtime libraries are installed when sanitizers are enabled",
+        severity: Severity::Warning,
+        cost: Cost::Probe,
+        run: check_sanitizers,
+        after: &[],
+    },
+    Check {
+        name: "profiler-runtime",
+        description: "the profiler runtime sources are present when build.profiler is enabled",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_profiler,
+        after: &[],
+    },
+    Check {
+        name: "llvm-profdata",
+        description: "llvm-profdata is next to an external LLVM when build.profiler is enabled",
+        severity: Severity::Warning,
+        cost: Cost::Static,
+        run: check_llvm_profdata,
+        after: &[],
+    },
+    Check {
+        name: "coverage-tools",
+        description: "llvm-profdata and llvm-cov next to llvm-config come from the same LLVM \
+                      release as the compiler's when build.profiler is enabled",
+        severity: Severity::Error,
+        cost: Cost::Probe,
+        run: check_coverage_tools,
+        after: &["llvm-profdata"],
+    },
+    Check {
+        name: "llvm-backends",
+        description: "an external LLVM was built with the backend of every target",
+        severity: Severity::Error,
+        cost: Cost::Probe,
+        run: check_llvm_backends,
+        after: &[],
+    },
+    Check {
+        name: "env-overrides",
+        description: "environment variables like CC don't disagree with the configuration",
+        severity: Severity::Warning,
+        cost: Cost::Static,
+        run: check_env_overrides,
+        after: &[],
+    },
+    Check {
+        name: "jemalloc-hosts",
+        description: "notes hosts which don't use jemalloc even though it's enabled",
+        severity: Severity::Note,
+        cost: Cost::Static,
+        run: check_jemalloc_hosts,
+        after: &[],
+    },
+    Check {
+        name: "filecheck",
+        description: "FileCheck exists when using an external LLVM with codegen tests",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_filecheck,
+        after: &[],
+    },
+    Check {
+        name: "llvm-assertions-tests",
+        description: "notes LLVM assertions being built while codegen tests are disabled",
+        severity: Severity::Note,
+        cost: Cost::Static,
+        run: check_llvm_assertions_tests,
+        after: &[],
+    },
+    Check {
+        name: "ios-host",
+        description: "iOS targets are only built on macOS",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_ios_host,
+        after: &[],
+    },
+    Check {
+        name: "apple-sdk",
+        description: "Apple targets have an SDK new enough for their deployment target",
+        severity: Severity::Error,
+        cost: Cost::Probe,
+        run: check_apple_sdks,
+        after: &[],
+    },
+    Check {
+        name: "android-api",
+        description: "Android toolchains target a new enough API level",
+        severity: Severity::Error,
+        cost: Cost::Probe,
+        run: check_android_api_levels,
+        after: &["c-compilers"],
+    },
+    Check {
+        name: "no-std-targets",
+        description: "*-none-* targets are configured as no_std",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_no_std_targets,
+        after: &[],
+    },
+    Check {
+        name: "musl-root",
+        description: "musl targets have a musl-root containing libc.a and libunwind.a",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_musl_root,
+        after: &[],
+    },
+    Check {
+        name: "musl-root-endianness",
+        description: "musl-root objects have the same endianness as their target",
+        severity: Severity::Warning,
+        cost: Cost::Static,
+        run: check_musl_root_endianness,
+        after: &["musl-root"],
+    },
+    Check {
+        name: "musl-root-arch",
+        description: "musl-root libraries are for the architecture of their target",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_musl_root_arch,
+        after: &["musl-root"],
+    },
+    Check {
+        name: "test-emulators",
+        description: "emulators for remotely tested targets exist when running tests",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_test_emulators,
+        after: &[],
+    },
+    Check {
+        name: "archive-tools",
+        description: "tar and gzip are available when `distcheck` or \
+                      build.openssl-static unpacks tarballs",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_archive_tools,
+        after: &[],
+    },
+    Check {
+        name: "sdk-targets",
+        description: "SDK-based targets like Fuchsia or Haiku have a complete SDK configured",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_sdk_targets,
+        after: &[],
+    },
+    Check {
+        name: "cmake-msvc-generators",
+        description: "cmake supports Visual Studio generators for MSVC targets",
+        severity: Severity::Error,
+        cost: Cost::Probe,
+        run: check_cmake_msvc_generators,
+        after: &["cmake"],
+    },
+    Check {
+        name: "ccache",
+        description: "the configured llvm.ccache exists",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_ccache,
+        after: &[],
+    },
+    Check {
+        name: "ccache-sanitizers",
+        description: "ccache is configured to cache sanitizer builds correctly",
+        severity: Severity::Warning,
+        cost: Cost::Static,
+        run: check_ccache_sanitizers,
+        after: &["ccache"],
+    },
+    Check {
+        name: "sccache",
+        description: "an llvm.ccache named sccache is sccache, and its server starts",
+        severity: Severity::Warning,
+        cost: Cost::Probe,
+        run: check_sccache,
+        after: &["ccache"],
+    },
+    Check {
+        name: "stage0-version",
+        description: "stage0.txt bootstraps from the release before this one",
+        severity: Severity::Warning,
+        cost: Cost::Static,
+        run: check_stage0_version,
+        after: &[],
+    },
+    Check {
+        name: "stage0-host",
+        description: "the stage0 compiler was built for the build machine's triple",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_stage0_host,
+        after: &[],
+    },
+    Check {
+        name: "stable-stage0",
+        description: "stable releases are bootstrapped from a released compiler",
+        severity: Severity::Error,
+        cost: Cost::Static,
+        run: check_stable_stage0,
+        after: &[],
+    },
+];
 
-This is likely due to it being an msys/cygwin build of cmake,
-rather than the required windows version, built using MinGW
-or Visual Studio.
+/// The set of checks `check` runs, chosen with `build.sanity-profile`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Profile {
+    /// Every check.
+    Full,
+    /// Only `MINIMAL_CHECKS`.
+    Minimal,
+}
 
-If you are building under msys2 try installing the mingw-w64-x86_64-cmake
-package instead of cmake:
+/// The checks of the minimal profile: those which don't need a C or C++
+/// toolchain, cmake, LLVM, emulators or SDKs.
+const MINIMAL_CHECKS: &[&str] = &[
+    "path-quotes",
+    "build-dir",
+    "clock-skew",
+    "relative-path",
+    "download-cache",
+    "temp-dir",
+    "forbidden-tools",
+    "python-sandbox",
+    "git",
+    "stage0-version",
+    "stable-stage0",
+];
 
-$ pacman -R cmake && pacman -S mingw-w64-x86_64-cmake
-");
+impl Profile {
+    fn from_config(config: &Config) -> Result<Profile, SanityError> {
+        match config.sanity_profile.as_ref().map(|p| &p[..]) {
+            None | Some("full") => Ok(Profile::Full),
+            Some("minimal") => Ok(Profile::Minimal),
+            Some(other) => {
+                Err(SanityError::new(ErrorKind::Config,
+                                     format!("unknown build.sanity-profile {:?}, expected \
+                                              \"full\" or \"minimal\"", other)))
             }
         }
     }
 
-    let run = |cmd: &mut Command| {
-        cmd.output().map(|output| {
-            String::from_utf8_lossy(&output.stdout)
-                   .lines().next().unwrap_or_else(|| {
-                       panic!("{:?} failed {:?}", cmd, output)
-                   }).to_string()
-        })
-    };
-    build.lldb_version = run(Command::new("lldb").arg("--version")).ok();
-    if build.lldb_version.is_some() {
-        build.lldb_python_dir = run(Command::new("lldb").arg("-P")).ok();
+    fn includes(&self, check: &str) -> bool {
+        match *self {
+            Profile::Full => true,
+            Profile::Minimal => MINIMAL_CHECKS.contains(&check),
+        }
+    }
+
+    /// The smallest profile including `check`.
+    fn of(check: &str) -> Profile {
+        if Profile::Minimal.includes(check) {
+            Profile::Minimal
+        } else {
+            Profile::Full
+        }
     }
+}
 
-    if let Some(ref s) = build.config.ccache {
-        cmd_finder.must_have(s);
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Profile::Full => "full",
+            Profile::Minimal => "minimal",
+        })
     }
+}
+
+/// Lists the name, default severity, smallest profile and description of
+/// every check, one per line.
+fn list_checks() -> String {
+    CHECKS.iter().map(|c| {
+        format!("{:<24} {:<8} {:<8} {:<7} {}\n", c.name, c.severity, Profile::of(c.name),
+                c.cost, c.description)
+    }).collect()
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Plan {
+    profile: String,
+    dry_run: bool,
+    checks: Vec<PlannedCheck>,
+}
+
+#[derive(Serialize)]
+struct PlannedCheck {
+    name: &'static str,
+    description: &'static str,
+    severity: String,
+    cost: String,
+    after: &'static [&'static str],
+    denied: bool,
+}
 
-    if build.config.channel == "stable" {
-        let mut stage0 = String::new();
-        t!(t!(File::open(build.src.join("src/stage0.txt")))
-            .read_to_string(&mut stage0));
-        if stage0.contains("\ndev:") {
-            panic!("bootstrapping from a dev compiler in a stable release, but \
-                    should only be bootstrapping from a released compiler!");
+/// Returns the checks which would run with `config` as JSON, for tools
+/// presenting them, in the order they run:
+///
+/// ```json
+/// {"profile": "full", "dry-run": false, "checks": [
+///   {"name": "c-compilers", "description": "...", "severity": "error",
+///    "cost": "static", "after": [], "denied": false},
+///   ...
+/// ]}
+/// ```
+///
+/// `denied` says whether the check's warnings are promoted to errors by
+/// `build.deny-sanity-warnings`.
+fn check_plan(config: &Config, profile: Profile) -> String {
+    let checks = CHECKS.iter().filter(|c| c.runs(profile, config.dry_run)).map(|c| {
+        PlannedCheck {
+            name: c.name,
+            description: c.description,
+            severity: c.severity.to_string(),
+            cost: c.cost.to_string(),
+            after: c.after,
+            denied: config.deny_sanity_warnings.iter().any(|name| name == c.name),
         }
+    }).collect();
+    let plan = Plan { profile: profile.to_string(), dry_run: config.dry_run, checks };
+    serde_json::to_string_pretty(&plan).expect("the plan is always valid JSON") + "\n"
+}
+
+/// How `check` or `try_check` ended, if nothing failed.
+#[derive(Debug, PartialEq)]
+pub enum Checked {
+    /// The configuration is filled in and the build can go on.
+    Ready,
+    /// A mode like `--list-checks` or `--sanity-only` did what was asked of
+    /// it, so the caller should print `output` and exit with `code` rather
+    /// than build anything.
+    Stop { output: String, code: i32 },
+}
+
+/// Runs `try_check`, writing the result out with `result_json` for
+/// `--sanity-json`.
+///
+/// With `--sanity-only` this asks the caller to stop if every check passed.
+/// Errors are left to the caller to print, and to exit with the exit code of
+/// their kind.
+pub fn check(build: &mut Build, resolver: Option<Box<ToolResolver>>)
+             -> Result<Checked, SanityError> {
+    let mut result = try_check(build, resolver);
+    if let Some(ref path) = build.config.sanity_json {
+        // The failure which is being reported is the more interesting one.
+        let written = write_output("--sanity-json", path, &result_json(&result));
+        result = result.and_then(|checked| written.map(|()| checked));
+    }
+    match result {
+        Ok(Checked::Ready) if build.config.sanity_only => {
+            Ok(Checked::Stop { output: String::new(), code: 0 })
+        }
+        result => result,
+    }
+}
+
+/// Writes `contents` to `path`, the file `what` asked for.
+fn write_output(what: &str, path: &Path, contents: &str) -> Result<(), SanityError> {
+    File::create(path).and_then(|mut f| f.write_all(contents.as_bytes())).map_err(|e| {
+        SanityError::new(ErrorKind::Permission,
+                         format!("failed to write {} for {}: {}", path.display(), what, e))
+    })
+}
+
+#[derive(Serialize, Default)]
+#[serde(rename_all = "kebab-case")]
+struct ResultJson<'a> {
+    ok: bool,
+    exit_code: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kind: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    check: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subject: Option<Option<&'a str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<&'a str>,
+}
+
+/// Returns the outcome of `try_check` as JSON, for scripts which want more
+/// than the exit code:
+///
+/// ```json
+/// {"ok": false, "exit-code": 2, "kind": "missing-tool", "check": "cmake",
+///  "subject": "cmake", "message": "..."}
+/// ```
+///
+/// `check` is empty if no check had started, and `subject` is `null` if the
+/// failure isn't about a particular tool or target. On success only `ok` and
+/// `exit-code` are present.
+fn result_json<T>(result: &Result<T, SanityError>) -> String {
+    let json = match *result {
+        Ok(_) => ResultJson { ok: true, exit_code: 0, ..ResultJson::default() },
+        Err(ref e) => ResultJson {
+            ok: false,
+            exit_code: e.kind.exit_code(),
+            kind: Some(e.kind.name()),
+            check: Some(e.check),
+            subject: Some(e.subject.as_ref().map(|s| &s[..])),
+            message: Some(e.message.trim()),
+        },
+    };
+    serde_json::to_string(&json).expect("the result is always valid JSON") + "\n"
+}
+
+/// Fills in the auto-detected parts of the configuration and then runs
+/// every check, stopping at the first one which fails. Missing commands are
+/// the exception, they're all reported together once every check has run.
+///
+/// Modes which only print something, like `--list-checks`, return what to
+/// print as `Checked::Stop` instead of building.
+pub fn try_check(build: &mut Build, resolver: Option<Box<ToolResolver>>)
+                 -> Result<Checked, SanityError> {
+    if build.config.list_checks {
+        return Ok(Checked::Stop { output: list_checks(), code: 0 })
+    }
+
+    for name in &build.config.deny_sanity_warnings {
+        if !CHECKS.iter().any(|c| c.name == *name) {
+            return Err(SanityError::new(ErrorKind::Config,
+                                        format!("unknown sanity check {:?} in \
+                                                 build.deny-sanity-warnings, see \
+                                                 `--list-checks` for all of them", name)))
+        }
+    }
+
+    // The cache is only valid for what the default resolver finds.
+    let tool_cache = if resolver.is_none() && env::var_os(NO_TOOL_CACHE_VAR).is_none() {
+        Some((build.out.join(TOOL_CACHE_FILE), tool_cache_key(&build.config)))
+    } else {
+        None
+    };
+    let resolver = match resolver {
+        Some(resolver) => resolver,
+        None => path_resolver(build)?,
+    };
+    validate_versions(&build.config)?;
+    validate_conflicts(&build.config)?;
+    let profile = Profile::from_config(&build.config)?;
+    if build.config.sanity_plan {
+        return Ok(Checked::Stop { output: check_plan(&build.config, profile), code: 0 })
+    }
+
+    let mut finder = Finder::with_resolver(resolver);
+    finder.interactive = build.ci_env == CiEnv::None;
+    finder.build = Some(build.build);
+    if let Some((ref file, ref key)) = tool_cache {
+        let loaded = finder.load_cache(file, key);
+        build.verbose(&format!("sanity: loaded {} commands from {}", loaded, file.display()));
+    }
+
+    // First fill in everything that's auto-detected, as both the checks
+    // below and the rest of the build depend on that.
+    let start = Instant::now();
+    let mutations = detect(build, &mut finder)?;
+    let detect_time = start.elapsed();
+    if build.config.sanity_diff {
+        return Ok(Checked::Stop { output: mutations.to_string(), code: 0 })
+    }
+    mutations.apply(build);
+
+    if building_llvm(build) {
+        for host in &build.hosts {
+            build.verbose(&format!("sanity: LLVM for {} will be built with the {} \
+                                    cmake generator",
+                                   host, cmake_generator(&build.config, host)));
+        }
+    }
+
+    // Only detection fills in the configuration the rest of the build relies
+    // on, the checks just validate it.
+    if is_truthy(env::var_os(SKIP_SANITY_VAR)) {
+        println!("warning: {} is set, so the sanity checks were skipped; a missing, broken \
+                  or outdated tool will only fail the build once it's needed, possibly hours \
+                  in and with a far less helpful error",
+                 SKIP_SANITY_VAR);
+        return Ok(Checked::Ready)
+    }
+
+    let warnings = Warnings::new(build.config.deny_sanity_warnings.clone(), false);
+    let mut sanity = Sanity { build, finder, warnings };
+    let mut check_timings = vec![("detection".to_string(), detect_time)];
+    let dry_run = sanity.build.config.dry_run;
+    // The checks which couldn't find a command they require. What builds on
+    // them is skipped, as it'd only fail for the same reason.
+    let mut incomplete = Vec::new();
+    for c in CHECKS.iter().filter(|c| c.runs(profile, dry_run)) {
+        if c.after.iter().any(|name| incomplete.contains(name)) {
+            incomplete.push(c.name);
+            continue
+        }
+        let missing = sanity.finder.missing.len();
+        let start = Instant::now();
+        if let Err(e) = run_check(&mut sanity, c) {
+            sanity.warnings.flush();
+            return Err(e)
+        }
+        check_timings.push((c.name.to_string(), start.elapsed()));
+        if sanity.finder.missing.len() > missing {
+            incomplete.push(c.name);
+        }
+    }
+    sanity.warnings.flush();
+    if let Some((ref file, ref key)) = tool_cache {
+        if !dry_run {
+            // Without the cache the next invocation is only a bit slower.
+            let _ = sanity.finder.save_cache(file, key);
+        }
+    }
+    if let Some(path) = env::var_os(TOOL_REPORT_VAR) {
+        let report = tool_report(sanity.build, &sanity.finder);
+        write_output(TOOL_REPORT_VAR, Path::new(&path), &report)?;
+    }
+    sanity.finder.finish()?;
+
+    if sanity.build.config.print_sanity_timings {
+        print_timings("check", &mut check_timings);
+        PROBE_TIMINGS.with(|timings| print_timings("probe", &mut timings.borrow_mut()));
+    }
+
+    let promoted = sanity.warnings.promoted.len();
+    if promoted > 0 {
+        return Err(SanityError::new(ErrorKind::DeniedWarnings,
+                                    format!("\n\n{} sanity check warning(s) were promoted \
+                                             to errors by build.deny-sanity-warnings\n\n",
+                                            promoted)))
+    }
+
+    if let Some(ref path) = sanity.build.config.sanity_export {
+        write_output("--sanity-export", path, &pinned_config(sanity.build))?;
+        println!("sanity: wrote the detected environment to {}", path.display());
+    }
+    Ok(Checked::Ready)
+}
+
+/// The environment variable which, when set to something like `1` or
+/// `true`, makes `check` only detect the configuration and skip the checks.
+const SKIP_SANITY_VAR: &str = "BOOTSTRAP_SKIP_SANITY";
+
+/// Returns whether the environment variable `value` is set to something
+/// meaning yes, rather than to nothing, `0` or `false`.
+fn is_truthy(value: Option<OsString>) -> bool {
+    let value = match value {
+        Some(value) => value.to_string_lossy().trim().to_lowercase(),
+        None => return false,
+    };
+    ["1", "true", "yes", "on"].contains(&&value[..])
+}
+
+/// The environment variable naming a file `check` writes `tool_report` to.
+const TOOL_REPORT_VAR: &str = "BOOTSTRAP_SANITY_REPORT";
+
+#[derive(Serialize)]
+struct ReportedTool {
+    path: String,
+    version: Option<String>,
+}
+
+/// Returns every tool the checks resolved as JSON, keyed by name, with the
+/// version they probed if they did:
+///
+/// ```json
+/// {
+///   "cmake": {"path": "/usr/bin/cmake", "version": "cmake version 3.11.4"},
+///   "cc:x86_64-unknown-linux-gnu": {"path": "cc", "version": null}
+/// }
+/// ```
+///
+/// The C and C++ compilers and archivers are named after their targets.
+fn tool_report(build: &Build, finder: &Finder) -> String {
+    let mut tools = BTreeMap::new();
+    for (name, path) in &finder.cache {
+        if let Some(ref path) = *path {
+            tools.insert(name.to_string_lossy().into_owned(), path.clone());
+        }
+    }
+    // Detection may have picked these without looking up their names.
+    let configured = [
+        ("python", &build.config.python),
+        ("node", &build.config.nodejs),
+        ("gdb", &build.config.gdb),
+    ];
+    for &(name, path) in &configured {
+        if let Some(ref path) = *path {
+            tools.insert(name.to_string(), path.clone());
+        }
+    }
+    for (target, cc) in &build.cc {
+        tools.insert(format!("cc:{}", target), cc.path().to_path_buf());
+    }
+    for (target, cxx) in &build.cxx {
+        tools.insert(format!("cxx:{}", target), cxx.path().to_path_buf());
+    }
+    for (target, ar) in &build.ar {
+        tools.insert(format!("ar:{}", target), ar.clone());
+    }
+
+    let report = tools.into_iter().map(|(name, path)| {
+        let version = finder.versions.get(&path).cloned();
+        (name, ReportedTool { path: path.to_string_lossy().into_owned(), version })
+    }).collect::<BTreeMap<_, _>>();
+    serde_json::to_string_pretty(&report).expect("the report is always valid JSON") + "\n"
+}
+
+/// Quotes `s` as a TOML basic string.
+fn toml_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\t' => quoted.push_str("\\t"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04X}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn toml_path(path: &Path) -> String {
+    toml_string(&path.to_string_lossy())
+}
+
+fn toml_triples(triples: &[Interned<String>]) -> String {
+    let quoted = triples.iter().map(|t| toml_string(t)).collect::<Vec<_>>();
+    format!("[{}]", quoted.join(", "))
+}
+
+/// Renders what `check` detected, and what was configured already, as a
+/// `config.toml` snippet pinning every tool, so the same environment can be
+/// reproduced or reviewed. Versions which were probed are noted in comments.
+fn pinned_config(build: &Build) -> String {
+    let config = &build.config;
+    let mut out = String::new();
+    out.push_str("# The build environment detected by the sanity check.\n\n");
+
+    out.push_str("[build]\n");
+    out.push_str(&format!("build = {}\n", toml_string(&build.build)));
+    out.push_str(&format!("host = {}\n", toml_triples(&build.hosts)));
+    out.push_str(&format!("target = {}\n", toml_triples(&build.targets)));
+    out.push_str(&format!("cargo = {}\n", toml_path(&build.initial_cargo)));
+    out.push_str(&format!("rustc = {}\n", toml_path(&build.initial_rustc)));
+    if let Some(ref python) = config.python {
+        out.push_str(&format!("python = {}\n", toml_path(python)));
+    }
+    if let Some(ref nodejs) = config.nodejs {
+        out.push_str(&format!("nodejs = {}", toml_path(nodejs)));
+        if let Some(ref version) = build.nodejs_version {
+            out.push_str(&format!("  # {}", version));
+        }
+        out.push('\n');
+    }
+    if let Some(ref gdb) = config.gdb {
+        out.push_str(&format!("gdb = {}\n", toml_path(gdb)));
+    }
+    if let Some(ref version) = build.gdb_version {
+        out.push_str(&format!("# gdb: {}\n", version));
+    }
+    if let Some(ref version) = build.lldb_version {
+        out.push_str(&format!("# lldb: {}\n", version.trim()));
+    }
+
+    out.push_str("\n[llvm]\n");
+    out.push_str(&format!("ninja = {}\n", config.ninja));
+    if let Some(ref ccache) = config.ccache {
+        out.push_str(&format!("ccache = {}", toml_string(ccache)));
+        if let Some(ref version) = build.sccache_version {
+            out.push_str(&format!("  # {}", version));
+        }
+        out.push('\n');
+    }
+
+    let mut targets = build.hosts.iter().chain(&build.targets).cloned().collect::<Vec<_>>();
+    targets.sort();
+    targets.dedup();
+    for target in targets {
+        out.push_str(&format!("\n[target.{}]\n", target));
+        if let Some(cc) = build.cc.get(&target) {
+            out.push_str(&format!("cc = {}\n", toml_path(cc.path())));
+        }
+        if let Some(cxx) = build.cxx.get(&target) {
+            out.push_str(&format!("cxx = {}\n", toml_path(cxx.path())));
+        }
+        if let Some(ar) = build.ar.get(&target) {
+            out.push_str(&format!("ar = {}\n", toml_path(ar)));
+        }
+        let target_config = match config.target_config.get(&target) {
+            Some(target_config) => target_config,
+            None => continue,
+        };
+        let paths = [
+            ("linker", &target_config.linker),
+            ("llvm-config", &target_config.llvm_config),
+            ("musl-root", &target_config.musl_root),
+            ("sdk", &target_config.sdk),
+        ];
+        for &(key, path) in &paths {
+            if let Some(ref path) = *path {
+                out.push_str(&format!("{} = {}\n", key, toml_path(path)));
+            }
+        }
+        if target_config.no_std {
+            out.push_str("no-std = true\n");
+        }
+    }
+    out
+}
+
+/// Runs the check `c`, attributing its warnings and errors to it.
+fn run_check(sanity: &mut Sanity, c: &Check) -> Result<(), SanityError> {
+    sanity.warnings.check = c.name;
+    (c.run)(sanity).map_err(|mut e| {
+        e.check = c.name;
+        e
+    })
+}
+
+/// Returns the default resolver, which searches `build.tools-bin` and then
+/// `PATH`.
+fn path_resolver(build: &Build) -> Result<Box<ToolResolver>, SanityError> {
+    let mut resolver = PathResolver::new();
+    resolver.verbose = build.is_verbose();
+    resolver.skip_relative = build.config.strict_path;
+    if let Some(ref dir) = build.config.tools_bin {
+        if !dir.is_dir() {
+            return Err(SanityError::new(ErrorKind::Config,
+                                        format!("the build.tools-bin directory {} doesn't \
+                                                 exist", dir.display())))
+        }
+        resolver.prepend(dir.clone(), "build.tools-bin");
+    }
+    Ok(Box::new(resolver))
+}
+
+/// The oldest cmake LLVM's `cmake_minimum_required` accepts.
+const MIN_CMAKE_VERSION: (u64, u64, u64) = (3, 4, 3);
+
+/// The oldest gdb the debuginfo tests can load the Rust pretty-printers into,
+/// as it's the first with `add-auto-load-safe-path`.
+const MIN_GDB_VERSION: (u64, u64, u64) = (7, 4, 0);
+
+/// The oldest node.js the rustdoc-js tests are known to work with.
+const MIN_NODEJS_VERSION: (u64, u64, u64) = (8, 0, 0);
+
+/// The cmake generator native code like LLVM is built with.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CmakeGenerator {
+    Ninja,
+    UnixMakefiles,
+    MinGWMakefiles,
+    MsysMakefiles,
+    /// Whichever version of Visual Studio cmake finds, the newest by default.
+    VisualStudio,
+}
+
+impl fmt::Display for CmakeGenerator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            CmakeGenerator::Ninja => "Ninja",
+            CmakeGenerator::UnixMakefiles => "Unix Makefiles",
+            CmakeGenerator::MinGWMakefiles => "MinGW Makefiles",
+            CmakeGenerator::MsysMakefiles => "MSYS Makefiles",
+            CmakeGenerator::VisualStudio => "Visual Studio",
+        })
+    }
+}
+
+/// Returns the cmake generator used when building native code for `target`.
+///
+/// Only `Ninja` is asked for explicitly, the rest are what the `cmake` crate
+/// and cmake itself pick by default.
+pub fn cmake_generator(config: &Config, target: &str) -> CmakeGenerator {
+    if config.ninja {
+        CmakeGenerator::Ninja
+    } else if target.contains("msvc") {
+        CmakeGenerator::VisualStudio
+    } else if target.contains("windows-gnu") && config.build.contains("windows") {
+        if env::var_os("MSYSTEM").is_some() {
+            CmakeGenerator::MsysMakefiles
+        } else {
+            CmakeGenerator::MinGWMakefiles
+        }
+    } else {
+        CmakeGenerator::UnixMakefiles
+    }
+}
+
+/// Returns whether jemalloc can be used on `target`, whatever
+/// `rust.use-jemalloc` says.
+///
+/// It isn't used on MSVC, which also avoids packaging the dummy
+/// liballoc_jemalloc there.
+pub fn jemalloc_supported(target: &str) -> bool {
+    !target.contains("msvc")
+}
+
+/// Splits `hosts` into those which will use jemalloc if it's enabled and
+/// those which won't.
+fn jemalloc_hosts<'a>(hosts: &[&'a str]) -> (Vec<&'a str>, Vec<&'a str>) {
+    hosts.iter().cloned().partition(|host| jemalloc_supported(host))
+}
+
+/// Returns whether we're building LLVM ourselves for any host.
+fn building_llvm(build: &Build) -> bool {
+    build.hosts.iter()
+        .filter_map(|host| build.config.target_config.get(host))
+        .any(|config| config.llvm_config.is_none())
+}
+
+/// Probes the environment for the configuration that's filled in
+/// automatically, returning the changes to make to `build.config`.
+fn detect(build: &mut Build, finder: &mut Finder) -> Result<Mutations, SanityError> {
+    let mut mutations = Mutations { list: Vec::new() };
+
+    // If ninja isn't enabled but we're building for MSVC then we try
+    // doubly hard to enable it. It was realized in #43767 that the msbuild
+    // CMake generator for MSVC doesn't respect configuration options like
+    // disabling LLVM assertions, which can often be quite important!
+    //
+    // In these cases we automatically enable Ninja if we find it in the
+    // environment.
+    if building_llvm(build) && !build.config.ninja && build.config.build.contains("msvc") {
+        if finder.maybe_have("ninja").is_some() {
+            mutations.push(build, Mutation::Ninja(true));
+        }
+    }
+
+    let python = finder.must_have_if_set(build.config.python.as_ref())?
+        .or_else(|| env::var_os("BOOTSTRAP_PYTHON").map(PathBuf::from)) // set by bootstrap.py
+        .or_else(|| find_python(finder))
+        .ok_or_else(|| {
+            SanityError::new(ErrorKind::MissingTool,
+                             "\n\ncouldn't find required command: \"python\"\n\n".to_string())
+                .about("python")
+        })?;
+    mutations.push(build, Mutation::Python(python));
+
+    // Debian and Ubuntu used to call node.js `nodejs`.
+    let nodejs = match finder.must_have_if_set(build.config.nodejs.as_ref())? {
+        Some(nodejs) => Some(nodejs),
+        None if build.config.require_optional_tools => {
+            Some(finder.must_have_one_of(&["node", "nodejs"])?)
+        }
+        None => finder.first_of(&["node", "nodejs"]),
+    };
+    let nodejs_version = nodejs.as_ref().and_then(|node| tool_version(&mut Command::new(node)));
+    if let (Some(node), Some(version)) = (nodejs.as_ref(), nodejs_version.as_ref()) {
+        finder.versions.insert(node.clone(), version.trim().to_string());
+    }
+    build.nodejs_version = nodejs_version.map(|v| v.trim().trim_left_matches('v').to_string());
+    mutations.push(build, Mutation::Nodejs(nodejs));
+
+    let gdb = finder.must_have_if_set(build.config.gdb.as_ref())?
+        .or_else(|| finder.maybe_have("gdb"));
+    build.gdb_version = gdb.as_ref()
+        .and_then(|gdb| quick_probe(Command::new(gdb).arg("--version")).ok())
+        .and_then(|out| gdb_version_line(&String::from_utf8_lossy(&out.stdout)));
+    if let (Some(gdb), Some(version)) = (gdb.as_ref(), build.gdb_version.as_ref()) {
+        finder.versions.insert(gdb.clone(), version.clone());
+    }
+    if let Some(why) = build.gdb_version.as_ref().and_then(|line| gdb_unsupported(line)) {
+        mutations.push(build, Mutation::GdbUnsupported(why));
+    }
+    mutations.push(build, Mutation::Gdb(gdb));
+
+    for target in &build.targets {
+        if target.contains("-none-") && build.no_std(*target).is_none() {
+            mutations.push(build, Mutation::NoStd(*target));
+        }
+
+        // If this is a native target (host is also musl) and no musl-root is given,
+        // fall back to the system toolchain in /usr before giving up
+        if target.contains("musl") && build.musl_root(*target).is_none() &&
+           build.config.build == *target {
+            mutations.push(build, Mutation::MuslRoot(*target, PathBuf::from("/usr")));
+        }
+
+        if let Some(sdk) = sdk_target(target) {
+            // Targets built against an SDK can also find it through the
+            // environment variable the SDK's own tooling uses.
+            let configured = build.config.target_config.get(target).and_then(|t| t.sdk.clone());
+            let root = configured.clone()
+                .or_else(|| sdk.env.and_then(env::var_os).map(PathBuf::from));
+            if let Some(root) = root {
+                if configured.is_none() {
+                    mutations.push(build, Mutation::Sdk(*target, root.clone()));
+                }
+                // What's missing is reported by "sdk-targets".
+                let paths = sdk_required_paths(sdk, target).iter()
+                    .map(|path| root.join(path))
+                    .collect::<Vec<_>>();
+                if paths.iter().all(|path| path.exists()) {
+                    mutations.push(build, Mutation::SdkPaths(*target, paths));
+                }
+            }
+        }
+    }
+
+    // An lldb which doesn't print anything, or hangs, is as good as none.
+    let run = |cmd: &mut Command| {
+        quick_probe(cmd).ok().and_then(|output| {
+            String::from_utf8_lossy(&output.stdout).lines().next().map(|s| s.to_string())
+        })
+    };
+    build.lldb_version = run(Command::new("lldb").arg("--version"));
+    if let Some(ref version) = build.lldb_version {
+        build.lldb_python_dir = run(Command::new("lldb").arg("-P"));
+        if let Some(lldb) = finder.maybe_have("lldb") {
+            finder.versions.insert(lldb, version.trim().to_string());
+        }
+    }
+    // The debuginfo tests import the module from there, and with a wrong
+    // directory every one of them fails for reasons that look unrelated,
+    // rather than saying the directory is missing.
+    let problem = build.lldb_python_dir.as_ref()
+        .and_then(|dir| lldb_python_dir_problem(Path::new(dir)).map(|p| (dir.clone(), p)));
+    if let Some((dir, problem)) = problem {
+        mutations.push(build, Mutation::NoLldbPythonDir(format!("`lldb -P` says lldb's Python \
+                                                                 module is in {}, but that {}",
+                                                                dir, problem)));
+    }
+
+    Ok(mutations)
+}
+
+/// The names `find_python` looks for, in order. Where `python` is Python 2,
+/// `python3` usually doesn't exist at all.
+const PYTHON_NAMES: &[&str] = &["python3", "python", "python2.7", "python2"];
+
+/// Searches `PATH` for a Python interpreter which actually runs, preferring
+/// Python 3. This skips things like the Microsoft Store stub Windows installs
+/// as `python` by default, or a leftover symlink to an uninstalled version.
+fn find_python(finder: &mut Finder) -> Option<PathBuf> {
+    for name in PYTHON_NAMES {
+        let python = match finder.maybe_have(name) {
+            Some(python) => python,
+            None => continue,
+        };
+        if runs_version(&python) {
+            return Some(python)
+        }
+        if cfg!(windows) && in_windows_apps(&python) {
+            println!("warning: ignoring {}, which is the Microsoft Store stub rather \
+                      than an actual Python installation", python.display());
+        } else {
+            println!("warning: ignoring {}, which fails to run `{} --version`",
+                     python.display(), name);
+        }
+    }
+    None
+}
+
+/// Returns whether `path` is one of the app execution aliases Windows puts
+/// in `%LOCALAPPDATA%\Microsoft\WindowsApps`.
+fn in_windows_apps(path: &Path) -> bool {
+    path.to_string_lossy()
+        .split(|c| c == '\\' || c == '/')
+        .any(|component| component.eq_ignore_ascii_case("WindowsApps"))
+}
+
+/// Returns whether `path --version` runs successfully. Python 2 prints its
+/// version to stderr, so only the exit status is looked at.
+fn runs_version(path: &Path) -> bool {
+    probe(Command::new(path).arg("--version"))
+        .map(|out| out.status.success())
+        .unwrap_or(false)
+}
+
+fn check_path_quotes(_: &mut Sanity) -> Result<(), SanityError> {
+    let path = env::var_os("PATH").unwrap_or_default();
+    // On Windows, quotes are invalid characters for filename paths, and if
+    // one is present as part of the PATH then that can lead to the system
+    // being unable to identify the files properly. See
+    // https://github.com/rust-lang/rust/issues/34959 for more details.
+    if cfg!(windows) && path.to_string_lossy().contains("\"") {
+        return Err(SanityError::new(ErrorKind::Environment,
+                                    "PATH contains invalid character '\"'".to_string()))
+    }
+    Ok(())
+}
+
+/// The most UTF-16 units an environment variable can hold on Windows.
+const WINDOWS_ENV_LIMIT: usize = 32767;
+
+/// The longest PATH which older APIs, and `cmd.exe` scripts expanding it,
+/// pass on without truncating.
+const WINDOWS_LEGACY_PATH_LIMIT: usize = 2047;
+
+/// Returns the limit a Windows PATH of `len` UTF-16 units is exceeding or, for
+/// the hard one, getting close to.
+fn path_length_limit(len: usize) -> Option<usize> {
+    if len > WINDOWS_ENV_LIMIT / 4 * 3 {
+        Some(WINDOWS_ENV_LIMIT)
+    } else if len > WINDOWS_LEGACY_PATH_LIMIT {
+        Some(WINDOWS_LEGACY_PATH_LIMIT)
+    } else {
+        None
+    }
+}
+
+fn check_path_length(s: &mut Sanity) -> Result<(), SanityError> {
+    if !cfg!(windows) {
+        return Ok(())
+    }
+    // We find tools with the whole PATH, but the ones spawned by cmake or a
+    // compiler driver may only get part of it, and then fail to find their
+    // own helpers now and again.
+    let len = env::var_os("PATH").unwrap_or_default().to_string_lossy().encode_utf16().count();
+    if let Some(limit) = path_length_limit(len) {
+        s.warnings.warn(format!("PATH is {} characters long, which is close to or over the {} \
+                                 that some programs pass on to the processes they spawn, so \
+                                 they may see it truncated; removing unused entries avoids \
+                                 intermittent failures to find tools", len, limit));
+    }
+    Ok(())
+}
+
+fn check_relative_path(s: &mut Sanity) -> Result<(), SanityError> {
+    // Which tool such an entry finds depends on where the build happens to
+    // be run from, and it's an easy way to pick up something unintended.
+    let path = env::var_os("PATH").unwrap_or_default();
+    let skipped = if s.build.config.strict_path { ", skipping it" } else { "" };
+    for dir in relative_path_entries(&path) {
+        s.warnings.warn(format!("PATH contains the relative entry {:?}, which is looked up \
+                                 from the current directory{}", dir, skipped));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn effective_uid() -> Option<u32> {
+    Some(unsafe { libc::geteuid() } as u32)
+}
+
+#[cfg(not(unix))]
+fn effective_uid() -> Option<u32> {
+    None
+}
+
+fn check_root_user(s: &mut Sanity) -> Result<(), SanityError> {
+    if s.build.config.allow_root {
+        return Ok(())
+    }
+    // One `sudo ./x.py build` leaves the build directory full of files only
+    // root can replace, which the next build as anyone else trips over.
+    if let Some(uid) = effective_uid() {
+        if uid == 0 {
+            s.warnings.warn(format!("building as root (uid {}) leaves files in {} which later \
+                                     builds as any other user can't replace; build as yourself, \
+                                     or set build.allow-root if that's intended",
+                                    uid, s.build.out.display()));
+        }
+    }
+    Ok(())
+}
+
+fn check_build_dir(s: &mut Sanity) -> Result<(), SanityError> {
+    // A build directory left behind by a `sudo` build is otherwise only
+    // noticed once the first thing is written to it.
+    ensure_writable_dir("build", &s.build.out)?;
+    Ok(())
+}
+
+/// Returns whether `path` is a UNC path like `\\server\share\rust` or its
+/// verbatim form `\\?\UNC\server\share\rust`, rather than one on a drive.
+fn is_unc_path(path: &str) -> bool {
+    let path = path.replace('/', "\\");
+    if path.starts_with("\\\\?\\") {
+        // `\\?\C:\rust` is on a drive, just spelled verbatim.
+        return path[4..].starts_with("UNC\\")
+    }
+    // `\\.\` names devices.
+    path.starts_with("\\\\") && !path.starts_with("\\\\.\\")
+}
+
+fn check_unc_paths(s: &mut Sanity) -> Result<(), SanityError> {
+    if !cfg!(windows) {
+        return Ok(())
+    }
+    // Older cmake and parts of MSVC's tools can't work in a directory
+    // without a drive letter, and fail with errors about the path that
+    // don't say why.
+    let dirs = [("source", &s.build.src), ("build", &s.build.out)];
+    for &(what, dir) in &dirs {
+        if is_unc_path(&dir.to_string_lossy()) {
+            s.warnings.warn(format!("the {} directory {} is a UNC path, which some versions of \
+                                     cmake and the MSVC tools don't support; mapping the share \
+                                     to a drive letter or building in a local directory avoids \
+                                     that", what, dir.display()));
+        }
+    }
+    Ok(())
+}
+
+/// How many seconds apart the system clock and file timestamps can be
+/// before the difference is worth pointing out.
+const MAX_CLOCK_SKEW_SECS: u64 = 5;
+
+/// Returns how far `mtime`, the timestamp of a file written between
+/// `before` and `after`, is ahead of (`true`) or behind (`false`) the system
+/// clock, if that's more than `MAX_CLOCK_SKEW_SECS`.
+fn clock_skew(before: SystemTime, mtime: SystemTime, after: SystemTime)
+              -> Option<(Duration, bool)> {
+    let max = Duration::from_secs(MAX_CLOCK_SKEW_SECS);
+    if let Ok(ahead) = mtime.duration_since(after) {
+        if ahead > max {
+            return Some((ahead, true))
+        }
+    }
+    if let Ok(behind) = before.duration_since(mtime) {
+        if behind > max {
+            return Some((behind, false))
+        }
+    }
+    None
+}
+
+fn check_clock_skew(s: &mut Sanity) -> Result<(), SanityError> {
+    // Network filesystems stamp files with the server's clock, and when
+    // that's off, artifacts look newer or older than they are, so what
+    // gets rebuilt stops making sense.
+    let probe = s.build.out.join(format!(".rustbuild-clock-{}", process::id()));
+    let before = SystemTime::now();
+    let mtime = File::create(&probe)
+        .and_then(|mut f| f.write_all(b"clock"))
+        .and_then(|_| fs::metadata(&probe))
+        .and_then(|m| m.modified());
+    let after = SystemTime::now();
+    let _ = fs::remove_file(&probe);
+    // Writing to the build directory at all is the build-dir check's job.
+    let mtime = match mtime {
+        Ok(mtime) => mtime,
+        Err(_) => return Ok(()),
+    };
+    if let Some((skew, ahead)) = clock_skew(before, mtime, after) {
+        s.warnings.warn(format!("files written to {} are stamped {}s {} the system clock, \
+                                 so incremental rebuilds may rebuild too much or too little; \
+                                 this usually means the clocks of a network filesystem's \
+                                 server and this machine disagree",
+                                s.build.out.display(), skew.as_secs(),
+                                if ahead { "ahead of" } else { "behind" }));
+    }
+    Ok(())
+}
+
+fn check_download_cache(s: &mut Sanity) -> Result<(), SanityError> {
+    // Downloaded artifacts are cached in `build/cache`, so make sure that's
+    // somewhere we can actually write to before anything tries to.
+    let cache_dir = s.build.out.join("cache");
+    let free = ensure_writable_dir("download cache", &cache_dir)?;
+    if let Some(free) = free {
+        s.build.verbose(&format!("download cache {} has {} free",
+                                 cache_dir.display(), human_size(free)));
+        if free < MIN_CACHE_DIR_SPACE {
+            s.warnings.warn(format!("only {} free in the download cache directory {}, \
+                                     downloads may fail", human_size(free), cache_dir.display()));
+        }
+    }
+    Ok(())
+}
+
+fn check_temp_dir(s: &mut Sanity) -> Result<(), SanityError> {
+    // A missing or read-only temporary directory otherwise shows up as
+    // baffling failures deep inside cmake or a C compiler.
+    let (dir, source) = resolve_temp_dir(&|name| env::var_os(name));
+    if !dir.is_dir() {
+        return Err(SanityError::new(ErrorKind::Environment,
+                                    format!("the temporary directory {} (from {}) doesn't \
+                                             exist", dir.display(), source)))
+    }
+    if let Err(e) = probe_writable(&dir) {
+        return Err(SanityError::new(ErrorKind::Permission,
+                                    format!("the temporary directory {} (from {}) is not \
+                                             writable: {}", dir.display(), source, e)))
+    }
+    if let Some(free) = free_space(&dir) {
+        s.build.verbose(&format!("temporary directory {} has {} free",
+                                 dir.display(), human_size(free)));
+        if free < MIN_TEMP_DIR_SPACE {
+            return Err(SanityError::new(ErrorKind::Environment,
+                                        format!("only {} free in the temporary directory {} \
+                                                 (from {})",
+                                                human_size(free), dir.display(), source)))
+        }
+    }
+    Ok(())
+}
+
+/// Returns the command printing the SHA-256 of a file on `build`, the same
+/// way native.rs checks the OpenSSL sources.
+fn sha256_command(build: &str) -> (&'static str, &'static [&'static str]) {
+    if build.contains("windows") {
+        ("certutil", &["-hashfile"])
+    } else if build.contains("apple") || build.contains("netbsd") {
+        ("shasum", &["-a", "256"])
+    } else {
+        ("sha256sum", &[])
+    }
+}
+
+/// Finds the hash in the output of `sha256_command`. `certutil` puts it on a
+/// line of its own, with spaces between the bytes on older versions.
+fn parse_sha256(output: &str) -> Option<String> {
+    let is_sha256 = |s: &str| s.len() == 64 && s.chars().all(|c| c.is_digit(16));
+    output.lines()
+        .filter_map(|line| {
+            let first = line.split_whitespace().next().unwrap_or("");
+            let joined = line.split_whitespace().collect::<String>();
+            if is_sha256(first) {
+                Some(first.to_lowercase())
+            } else if is_sha256(&joined) {
+                Some(joined.to_lowercase())
+            } else {
+                None
+            }
+        })
+        .next()
+}
+
+fn check_tool_checksums(s: &mut Sanity) -> Result<(), SanityError> {
+    if s.build.config.tool_checksums.is_empty() {
+        return Ok(())
+    }
+    let (hasher, args) = sha256_command(&s.build.build);
+    let hasher = s.finder.must_have(hasher)?;
+    let mut tools = s.build.config.tool_checksums.iter().collect::<Vec<_>>();
+    tools.sort();
+    for (tool, expected) in tools {
+        // Detection has already picked these, and may have done so without
+        // ever looking up their names.
+        let configured = match &tool[..] {
+            "python" => s.build.config.python.clone(),
+            "node" | "nodejs" => s.build.config.nodejs.clone(),
+            "gdb" => s.build.config.gdb.clone(),
+            _ => None,
+        };
+        let path = match configured {
+            Some(path) => path,
+            None => s.finder.must_have(tool)?,
+        };
+        let mut cmd = Command::new(&hasher);
+        cmd.args(args).arg(&path);
+        if s.build.build.contains("windows") {
+            cmd.arg("SHA256");
+        }
+        let found = probe(&mut cmd).ok()
+            .and_then(|out| {
+                if out.status.success() {
+                    parse_sha256(&String::from_utf8_lossy(&out.stdout))
+                } else {
+                    None
+                }
+            });
+        let found = match found {
+            Some(found) => found,
+            None => {
+                return Err(SanityError::new(ErrorKind::MissingTool,
+                                            format!("failed to hash {} with {}",
+                                                    path.display(), hasher.display()))
+                           .about(&hasher))
+            }
+        };
+        s.build.verbose(&format!("sanity: {} at {} has SHA-256 {}",
+                                 tool, path.display(), found));
+        if found != expected.to_lowercase() {
+            return Err(SanityError::new(ErrorKind::BadVersion,
+                                        format!("{} at {} doesn't have the SHA-256 listed \
+                                                 in build.tool-checksums\n\
+                                                 expected: {}\n\
+                                                 found:    {}",
+                                                tool, path.display(), expected, found))
+                       .about(tool))
+        }
+    }
+    Ok(())
+}
+
+fn check_forbidden_tools(s: &mut Sanity) -> Result<(), SanityError> {
+    // Some tools change what the build does just by being around, so
+    // hermetic builds can insist they aren't.
+    let mut found = Vec::new();
+    let mut dirs = None;
+    for tool in &s.build.config.forbidden_tools {
+        if !is_glob(tool) {
+            if let Some(path) = s.finder.maybe_have(tool) {
+                found.push(format!("    {} at {}", tool, path.display()));
+            }
+            continue
+        }
+        // Patterns can't be looked up, so list everything that's around
+        // instead, which only needs to happen once.
+        let dirs = dirs.get_or_insert_with(|| {
+            let path = env::var_os("PATH").unwrap_or_default();
+            s.build.config.tools_bin.iter().cloned()
+                .chain(env::split_paths(&path))
+                .collect::<Vec<_>>()
+        });
+        for path in programs_matching(dirs, tool) {
+            found.push(format!("    {} at {}", tool, path.display()));
+        }
+    }
+    if !found.is_empty() {
+        return Err(SanityError::new(ErrorKind::Environment,
+                                    format!("\n\nfound commands listed in \
+                                             build.forbidden-tools:\n{}\n\n",
+                                            found.join("\n"))))
+    }
+    Ok(())
+}
+
+/// Returns whether `pattern` has any of the wildcards `glob_match` supports.
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains('*') || pattern.contains('?')
+}
+
+/// Matches `name` against `pattern`, in which `*` matches any number of
+/// characters and `?` matches exactly one.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    // Where to resume after the last `*`, if matching after it fails.
+    let mut star = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            // Let the `*` swallow one more character and try again.
+            star = Some((star_p, star_n + 1));
+            p = star_p + 1;
+            n = star_n + 1;
+        } else {
+            return false
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Returns every file in `dirs` whose name, without any `.exe`, matches
+/// `pattern`.
+fn programs_matching(dirs: &[PathBuf], pattern: &str) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    for dir in dirs {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        let mut matches = entries.filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|path| path.is_file())
+            .filter(|path| {
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                let name = if cfg!(windows) { name.trim_right_matches(".exe") } else { &name };
+                glob_match(pattern, name)
+            })
+            .collect::<Vec<_>>();
+        matches.sort();
+        found.extend(matches);
+    }
+    found
+}
+
+/// Compiles a module to bytecode in a fresh directory and imports it, along
+/// with an extension module.
+const PYTHON_SANDBOX_PROBE: &str = "\
+import os, py_compile, shutil, sys, tempfile, zlib
+d = tempfile.mkdtemp()
+try:
+    src = os.path.join(d, 'rustbuild_sanity_probe.py')
+    with open(src, 'w') as f:
+        f.write('x = 1\\n')
+    py_compile.compile(src, doraise=True)
+    sys.path.insert(0, d)
+    import rustbuild_sanity_probe
+finally:
+    shutil.rmtree(d)
+";
+
+fn check_python_sandbox(s: &mut Sanity) -> Result<(), SanityError> {
+    // Sandboxes which forbid writing bytecode or loading native modules let
+    // python start but make our scripts fail intermittently, which is slow
+    // enough to find that it's only done when asked for.
+    if !s.build.config.sanity_probes {
+        return Ok(())
+    }
+    let python = s.build.python();
+    let mut cmd = Command::new(python);
+    cmd.arg("-c").arg(PYTHON_SANDBOX_PROBE);
+    let problem = match probe(&mut cmd) {
+        Ok(ref out) if out.status.success() => return Ok(()),
+        Ok(out) => String::from_utf8_lossy(&out.stderr).trim().to_string(),
+        Err(e) => e.to_string(),
+    };
+    s.warnings.warn(format!("{} couldn't compile and import a module, it may be running in \
+                             a restricted sandbox:\n{}", python.display(), problem));
+    Ok(())
+}
+
+fn check_git(s: &mut Sanity) -> Result<(), SanityError> {
+    // If we've got a git directory we're gonna need git to update
+    // submodules and learn about various other aspects.
+    if s.build.rust_info.is_git() {
+        let git = match s.finder.require("git")? {
+            Some(git) => git,
+            None => return Ok(()),
+        };
+        if !s.build.config.dry_run && !git_has_submodule(&git) {
+            return Err(SanityError::new(ErrorKind::MissingTool,
+                                        format!("the git found at {} can't run `git \
+                                                 submodule`, which is needed to check out \
+                                                 the submodules of this repository; this is \
+                                                 usually a minimal wrapper or a very old \
+                                                 git, so install a full git and put it first \
+                                                 in PATH", git.display())).about("git"))
+        }
+    }
+    Ok(())
+}
+
+/// Whether `git` has the `submodule` subcommand, which some stripped-down
+/// distributions and wrappers leave out.
+fn git_has_submodule(git: &Path) -> bool {
+    // Most installs ship it as a script in the exec path, which we can spot
+    // without running anything else.
+    if let Ok(out) = probe(Command::new(git).arg("--exec-path")) {
+        if out.status.success() {
+            let dir = String::from_utf8_lossy(&out.stdout);
+            if Path::new(dir.trim()).join("git-submodule").is_file() {
+                return true
+            }
+        }
+    }
+    // Otherwise ask the subcommand for its usage, which exits non-zero even
+    // when it exists, so look at what it printed instead. An unknown
+    // subcommand says "'submodule' is not a git command".
+    match probe(Command::new(git).args(&["submodule", "-h"])) {
+        Ok(out) => {
+            String::from_utf8_lossy(&out.stdout).contains("git submodule") ||
+                String::from_utf8_lossy(&out.stderr).contains("git submodule")
+        }
+        Err(_) => false,
+    }
+}
+
+fn check_cmake(s: &mut Sanity) -> Result<(), SanityError> {
+    // We need cmake, but only if we're actually building LLVM or sanitizers.
+    if !building_llvm(s.build) && !s.build.config.sanitizers {
+        return Ok(())
+    }
+    let cmake = match s.finder.require("cmake")? {
+        Some(cmake) => cmake,
+        None => return Ok(()),
+    };
+    // A dry run only checks what can be checked without running anything.
+    if s.build.config.dry_run {
+        return Ok(())
+    }
+    // A cmake which can't say its version fails soon enough on its own.
+    let line = match tool_version(&mut Command::new(&cmake)) {
+        Some(line) => line,
+        None => return Ok(()),
+    };
+    s.finder.versions.insert(cmake.clone(), line.trim().to_string());
+    let found = match cmake_version(&line) {
+        Some(found) => found,
+        None => return Ok(()),
+    };
+    if found < MIN_CMAKE_VERSION {
+        let (major, minor, patch) = found;
+        let (min_major, min_minor, min_patch) = MIN_CMAKE_VERSION;
+        return Err(SanityError::new(ErrorKind::BadVersion,
+                                    format!("\n\ncmake {}.{}.{} ({}) is older than {}.{}.{}, \
+                                             the oldest version LLVM can be built with\n\n",
+                                            major, minor, patch, cmake.display(),
+                                            min_major, min_minor, min_patch))
+            .about("cmake"))
+    }
+    Ok(())
+}
+
+fn check_ninja(s: &mut Sanity) -> Result<(), SanityError> {
+    // Ninja is currently only used for LLVM itself.
+    if building_llvm(s.build) && s.build.config.ninja {
+        // Some Linux distros rename `ninja` to `ninja-build`.
+        // CMake can work with either binary name.
+        s.finder.must_have_one_of(&["ninja", "ninja-build"])?;
+    }
+    Ok(())
+}
+
+/// Returns whether the tools of the extended build for `host` find OpenSSL
+/// with pkg-config, given `build.openssl-static`.
+fn needs_pkg_config(host: &str, openssl_static: bool) -> bool {
+    // Elsewhere openssl-sys isn't used, or finds OpenSSL on its own.
+    !openssl_static && !host.contains("windows") && !host.contains("apple") &&
+        !host.contains("emscripten")
+}
+
+/// Returns whether the environment tells openssl-sys where OpenSSL is for
+/// `host`, in which case it doesn't look for it with pkg-config.
+fn openssl_dir_set(host: &str) -> bool {
+    // openssl-sys prefers the variables prefixed with the target.
+    let prefix = host.to_uppercase().replace('-', "_");
+    let set = |var: &str| {
+        env::var_os(format!("{}_{}", prefix, var)).or_else(|| env::var_os(var)).is_some()
+    };
+    set("OPENSSL_DIR") || (set("OPENSSL_LIB_DIR") && set("OPENSSL_INCLUDE_DIR"))
+}
+
+/// Returns the pkg-config the pkg-config crate runs for `host`, if the
+/// environment overrides it, going through the same variables it does.
+fn pkg_config_override(host: &str, build: &str) -> Option<PathBuf> {
+    let vars = [format!("PKG_CONFIG_{}", host),
+                format!("PKG_CONFIG_{}", host.replace('-', "_")),
+                if host == build { "HOST_PKG_CONFIG" } else { "TARGET_PKG_CONFIG" }.to_string(),
+                "PKG_CONFIG".to_string()];
+    vars.iter().filter_map(|var| env::var_os(var)).find(|v| !v.is_empty()).map(PathBuf::from)
+}
+
+fn check_pkg_config(s: &mut Sanity) -> Result<(), SanityError> {
+    // cargo and the RLS link against OpenSSL through openssl-sys, which
+    // otherwise fails the build of the first of them with a wall of text,
+    // unless it finds OpenSSL under /usr or the like by itself.
+    if !s.build.config.extended {
+        return Ok(())
+    }
+    for host in &s.build.hosts {
+        if !needs_pkg_config(host, s.build.config.openssl_static) || openssl_dir_set(host) {
+            continue
+        }
+        // Cross toolchains usually come with a pkg-config which looks in
+        // their sysroot, named like their compilers.
+        let cross = format!("{}-pkg-config", host);
+        let found = match pkg_config_override(host, &s.build.build) {
+            Some(pkg_config) => s.finder.maybe_have(pkg_config),
+            None if *host != s.build.build => s.finder.first_of(&[&cross[..], "pkg-config"]),
+            None => s.finder.maybe_have("pkg-config"),
+        };
+        let pkg_config = match found {
+            Some(pkg_config) => pkg_config,
+            None => {
+                s.warnings.warn(format!("pkg-config{} wasn't found, but cargo for {} may need \
+                                         it to find OpenSSL; install it and the OpenSSL \
+                                         development package, set OPENSSL_DIR, or set \
+                                         build.openssl-static to build OpenSSL instead",
+                                        if *host == s.build.build {
+                                            String::new()
+                                        } else {
+                                            format!(" (or {})", cross)
+                                        },
+                                        host));
+                continue
+            }
+        };
+        if !s.build.config.dry_run {
+            let runs = probe(Command::new(&pkg_config).arg("--version"))
+                .map(|out| out.status.success())
+                .unwrap_or(false);
+            if !runs {
+                s.warnings.warn(format!("{} doesn't run, but cargo for {} may need it to find \
+                                         OpenSSL", pkg_config.display(), host));
+                continue
+            }
+        }
+        s.build.verbose(&format!("sanity: pkg-config for {} is {}", host, pkg_config.display()));
+    }
+    Ok(())
+}
+
+/// Returns what's wrong with `dir`, which `lldb -P` said its Python module
+/// is in, if anything.
+fn lldb_python_dir_problem(dir: &Path) -> Option<&'static str> {
+    if !dir.is_dir() {
+        Some("doesn't exist")
+    } else if !dir.join("lldb").join("__init__.py").is_file() {
+        Some("doesn't contain the `lldb` Python module")
+    } else {
+        None
+    }
+}
+
+fn check_lldb_python_dir(s: &mut Sanity) -> Result<(), SanityError> {
+    if let Some(ref why) = s.build.lldb_python_unusable {
+        s.warnings.warn(format!("{}, so it's not used and the lldb debuginfo tests can't run; \
+                                 is the lldb in PATH installed completely?", why));
+    }
+    Ok(())
+}
+
+/// Returns the line of the banner `gdb --version` prints which has the
+/// version, like `GNU gdb (Ubuntu 8.1-0ubuntu3) 8.1.0.20180409-git`.
+fn gdb_version_line(banner: &str) -> Option<String> {
+    banner.lines()
+        .find(|line| line.starts_with("GNU gdb"))
+        .or_else(|| banner.lines().next())
+        .map(|line| line.trim())
+        .and_then(|line| if line.is_empty() { None } else { Some(line.to_string()) })
+}
+
+/// Extracts gdb's version from `line`, its `gdb_version_line`.
+///
+/// Distributions put their own versions in parentheses before gdb's, like
+/// `GNU gdb (GDB; openSUSE 13.2) 7.8`, so this goes by the last word which
+/// has one instead of the first version in the line.
+fn gdb_version(line: &str) -> Option<(u64, u64, u64)> {
+    line.split_whitespace().rev().filter_map(parse_version).next()
+}
+
+/// Returns why the debuginfo-gdb tests can't run with the gdb whose
+/// `gdb_version_line` is `line`, if they can't.
+fn gdb_unsupported(line: &str) -> Option<String> {
+    let found = gdb_version(line)?;
+    if found >= MIN_GDB_VERSION {
+        return None
+    }
+    let (major, minor, patch) = found;
+    let (min_major, min_minor, min_patch) = MIN_GDB_VERSION;
+    Some(format!("gdb {}.{}.{} is older than {}.{}.{}, the oldest version which can load the \
+                  Rust pretty-printers",
+                 major, minor, patch, min_major, min_minor, min_patch))
+}
+
+fn check_gdb_version(s: &mut Sanity) -> Result<(), SanityError> {
+    if let Some(ref line) = s.build.gdb_version {
+        if gdb_version(line).is_none() {
+            s.build.verbose(&format!("couldn't parse the gdb version {:?}", line));
+        }
+    }
+    if let Some(ref why) = s.build.gdb_unsupported {
+        s.warnings.warn(format!("{}, so the debuginfo-gdb tests will be skipped", why));
+    }
+    Ok(())
+}
+
+fn check_nodejs_version(s: &mut Sanity) -> Result<(), SanityError> {
+    let version = match s.build.nodejs_version {
+        Some(ref version) => version,
+        None => return Ok(()),
+    };
+    // Nightly builds look like `11.0.0-nightly20180601e8b1ba2d9f` and the
+    // odd-numbered releases are never LTS, but both are just as usable as
+    // any other release so only the number itself matters.
+    let found = match parse_version(version) {
+        Some(found) => found,
+        None => {
+            s.build.verbose(&format!("couldn't parse the node.js version {:?}", version));
+            return Ok(())
+        }
+    };
+    if found < MIN_NODEJS_VERSION {
+        let (major, minor, patch) = MIN_NODEJS_VERSION;
+        let msg = format!("node.js {} is older than {}.{}.{}, the oldest version the \
+                           rustdoc-js tests work with", version, major, minor, patch);
+        if s.build.config.require_optional_tools {
+            return Err(SanityError::new(ErrorKind::BadVersion,
+                                        format!("\n\n{}\n\n", msg)).about("node"))
+        }
+        s.warnings.warn(msg);
+    }
+    Ok(())
+}
+
+/// How a tool is named in `config.toml`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ToolPath {
+    /// Like `/usr/bin/gcc`.
+    Absolute,
+    /// Like `gcc`, which is looked up in `PATH`.
+    Bare,
+    /// Like `bin/gcc` or `./gcc`, which could mean either of the others.
+    Relative,
+}
+
+impl ToolPath {
+    fn of(path: &Path) -> ToolPath {
+        if path.is_absolute() {
+            ToolPath::Absolute
+        } else if path.components().count() == 1 && path.parent() == Some(Path::new("")) {
+            ToolPath::Bare
+        } else {
+            ToolPath::Relative
+        }
+    }
+}
+
+/// Resolves `path`, the value of the `config.toml` key `key`, to the tool
+/// it names: bare names are looked up in `PATH` and relative paths are
+/// taken relative to `cwd`, as they are when cc or the build runs them.
+fn resolve_tool_path(finder: &mut Finder, cwd: &Path, key: &str, path: &Path)
+                     -> Result<PathBuf, SanityError> {
+    // What the finder finds is taken at its word, like everywhere else.
+    let (resolved, looked) = match ToolPath::of(path) {
+        ToolPath::Absolute => {
+            (Some(path.to_path_buf()).filter(|p| p.is_file()), String::new())
+        }
+        ToolPath::Bare => (finder.maybe_have(path), " in PATH".to_string()),
+        ToolPath::Relative => {
+            (Some(cwd.join(path)).filter(|p| p.is_file()),
+             format!(" relative to {}", cwd.display()))
+        }
+    };
+    resolved.ok_or_else(|| {
+        SanityError::new(ErrorKind::Config,
+                         format!("{} is set to {}, but it wasn't found{}",
+                                 key, path.display(), looked))
+            .about(path)
+    })
+}
+
+fn check_tool_paths(s: &mut Sanity) -> Result<(), SanityError> {
+    // Sections for targets which aren't built are never read.
+    let mut targets = s.build.hosts.iter().chain(&s.build.targets).cloned()
+        .filter(|t| s.build.config.target_config.contains_key(t))
+        .collect::<Vec<_>>();
+    targets.sort();
+    targets.dedup();
+    let cwd = env::current_dir().unwrap_or_else(|_| s.build.out.clone());
+    for target in targets {
+        // Emscripten's compilers are found by emcc instead.
+        if target.contains("emscripten") {
+            continue
+        }
+        for &name in &["cc", "cxx", "ar", "linker"] {
+            let path = {
+                let config = &s.build.config.target_config[&target];
+                let path = match name {
+                    "cc" => &config.cc,
+                    "cxx" => &config.cxx,
+                    "ar" => &config.ar,
+                    _ => &config.linker,
+                };
+                match *path {
+                    Some(ref path) => path.clone(),
+                    None => continue,
+                }
+            };
+            let key = format!("target.{}.{}", target, name);
+            let resolved = resolve_tool_path(&mut s.finder, &cwd, &key, &path)?;
+            if ToolPath::of(&path) != ToolPath::Relative {
+                continue
+            }
+            // Everything run from another directory, like cmake, looks for
+            // it somewhere else again.
+            s.warnings.warn(format!("{} is the relative path {}, which is {} from here but \
+                                     something else for tools run from another directory, \
+                                     like cmake; use an absolute path instead",
+                                    key, path.display(), resolved.display()));
+        }
+    }
+    Ok(())
+}
+
+fn check_c_compilers(s: &mut Sanity) -> Result<(), SanityError> {
+    // We're gonna build some custom C code here and there, host triples
+    // also build some C++ shims for LLVM so we need a C++ compiler.
+    for target in &s.build.targets {
+        // On emscripten we don't actually need the C compiler to just
+        // build the target artifacts, only for testing. For the sake
+        // of easier bot configuration, just skip detection.
+        if target.contains("emscripten") {
+            continue;
+        }
+
+        s.finder.require(s.build.cc(*target))?;
+        if let Some(ar) = s.build.ar(*target) {
+            s.finder.require(ar)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns whether `cc` is GCC or a cross compiler built from it, going by
+/// its name, like `gcc`, `g++-7` or `arm-linux-gnueabihf-gcc`.
+fn is_gcc_family(cc: &Path) -> bool {
+    cc.file_stem()
+        .and_then(|name| name.to_str())
+        .map_or(false, |name| name.contains("gcc") || name.contains("g++"))
+}
+
+/// Returns the archiver which comes with the GCC cross compiler `cc` for
+/// `target`, like `arm-linux-gnueabihf-ar`, if `ar` is the unprefixed one of
+/// the host instead.
+fn expected_cross_ar(target: &str, cc: &Path, ar: &Path) -> Option<String> {
+    // `cc_detect::cc2ar` deliberately picks the host's `ar` for these, and
+    // builds for them work with it.
+    if target.contains("musl") || target.contains("openbsd") {
+        return None
+    }
+    if !is_gcc_family(cc) || ar.file_stem().and_then(|name| name.to_str()) != Some("ar") {
+        return None
+    }
+    let name = cc.file_stem()?.to_str()?;
+    let prefix = &name[..name.rfind("gcc").or_else(|| name.rfind("g++"))?];
+    if prefix.is_empty() {
+        None
+    } else {
+        Some(format!("{}ar", prefix))
+    }
+}
+
+fn check_cross_archivers(s: &mut Sanity) -> Result<(), SanityError> {
+    for target in &s.build.targets {
+        // An explicitly configured `ar` is taken at its word.
+        let configured = s.build.config.target_config.get(target)
+            .map_or(false, |t| t.ar.is_some());
+        if *target == s.build.build || configured || target.contains("emscripten") {
+            continue
+        }
+        let ar = match s.build.ar(*target) {
+            Some(ar) => ar,
+            None => continue,
+        };
+        // The host's `ar` only knows about the host's object format, so the
+        // archives it makes for another architecture are broken in ways the
+        // linker reports much later, if at all.
+        if let Some(expected) = expected_cross_ar(target, s.build.cc(*target), ar) {
+            return Err(SanityError::new(ErrorKind::Config,
+                                        format!("the C compiler for {} ({}) is a GCC cross \
+                                                 compiler, but archives would be created \
+                                                 with {}, which is the host's; set \
+                                                 target.{}.ar in config.toml, probably to {}",
+                                                target, s.build.cc(*target).display(),
+                                                ar.display(), target, expected))
+                .about(target))
+        }
+    }
+    Ok(())
+}
+
+/// Returns the ELF `e_machine` of objects for `target`, or `None` if it
+/// doesn't use ELF or we don't know.
+fn target_elf_machine(target: &str) -> Option<u16> {
+    if target.contains("apple") || target.contains("windows") || target.contains("wasm") ||
+       target.contains("emscripten") {
+        return None
+    }
+    let arch = target.split('-').next().unwrap_or("");
+    let machine = match arch {
+        "sparc" => 2,
+        "i386" | "i586" | "i686" => 3,
+        "powerpc" => 20,
+        "powerpc64" | "powerpc64le" => 21,
+        "s390x" => 22,
+        "sparc64" | "sparcv9" => 43,
+        "x86_64" => 62,
+        "aarch64" => 183,
+        _ if arch.starts_with("mips") => 8,
+        _ if arch.starts_with("arm") || arch.starts_with("thumb") => 40,
+        _ if arch.starts_with("riscv") => 243,
+        _ => return None,
+    };
+    Some(machine)
+}
+
+/// Names the ELF `e_machine` value `machine` like `target_elf_machine`'s
+/// architectures, or by the number if it isn't one of them.
+fn elf_machine_name(machine: u16) -> String {
+    let name = match machine {
+        2 => "sparc",
+        3 => "x86",
+        8 => "mips",
+        20 => "powerpc",
+        21 => "powerpc64",
+        22 => "s390x",
+        40 => "arm",
+        43 => "sparc64",
+        62 => "x86_64",
+        183 => "aarch64",
+        243 => "riscv",
+        _ => return format!("ELF machine {}", machine),
+    };
+    name.to_string()
+}
+
+/// Reads `e_machine` out of `header`, the start of an ELF file.
+fn elf_machine(header: &[u8]) -> Option<u16> {
+    if header.len() < 20 || &header[..4] != b"\x7fELF" {
+        return None
+    }
+    let (a, b) = (header[18] as u16, header[19] as u16);
+    // EI_DATA
+    match header[5] {
+        1 => Some(a | b << 8),
+        2 => Some(a << 8 | b),
+        _ => None,
+    }
+}
+
+/// Creates the directory compiler probes write their sources and objects
+/// to, returning it.
+fn probe_dir(build: &Build) -> Result<PathBuf, SanityError> {
+    let dir = build.out.join("tmp");
+    if let Err(e) = fs::create_dir_all(&dir) {
+        return Err(SanityError::new(ErrorKind::Permission,
+                                    format!("failed to create the directory for compiler \
+                                             probes {}: {}", dir.display(), e)))
+    }
+    Ok(dir)
+}
+
+/// Writes `contents` to `path`, the source of a compiler probe.
+fn write_probe_source(path: &Path, contents: &[u8]) -> Result<(), SanityError> {
+    File::create(path).and_then(|mut f| f.write_all(contents)).map_err(|e| {
+        SanityError::new(ErrorKind::Permission,
+                         format!("failed to write the compiler probe {}: {}", path.display(), e))
+    })
+}
+
+fn check_cc_target_arches(s: &mut Sanity) -> Result<(), SanityError> {
+    if !s.build.config.sanity_probes {
+        return Ok(())
+    }
+    let dir = probe_dir(s.build)?;
+    for target in &s.build.targets {
+        let expected = match target_elf_machine(target) {
+            Some(expected) => expected,
+            None => continue,
+        };
+        // Launchers like distcc and icecc pass the compiler's arguments on,
+        // but wrappers which rebuild the command line can drop the flags
+        // that select the target, which the object file gives away.
+        let src = dir.join(format!("sanity-cc-arch-{}.c", target));
+        let obj = dir.join(format!("sanity-cc-arch-{}.o", target));
+        write_probe_source(&src, b"int rust_sanity_probe;\n")?;
+        let cc = s.build.cc(*target);
+        let out = probe(Command::new(cc).args(&s.build.cflags(*target))
+                                        .arg("-c").arg(&src).arg("-o").arg(&obj));
+        let mut header = Vec::new();
+        let read = File::open(&obj).and_then(|f| f.take(20).read_to_end(&mut header));
+        let _ = fs::remove_file(&src);
+        let _ = fs::remove_file(&obj);
+        // A compiler which can't build this at all is reported elsewhere.
+        match out {
+            Ok(ref out) if out.status.success() && read.is_ok() => {}
+            _ => continue,
+        }
+        match elf_machine(&header) {
+            Some(found) if found != expected => {
+                s.warnings.warn(format!("the C compiler for {} ({}) produced an object for \
+                                         {} rather than {}, the architecture {} needs; if it's \
+                                         a wrapper it may be dropping the target's flags ({})",
+                                        target, cc.display(), elf_machine_name(found),
+                                        elf_machine_name(expected), target,
+                                        s.build.cflags(*target).join(" ")));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// A program using the C99 features the C code we build relies on, which
+/// compilers defaulting to C89 reject.
+const C99_PROBE: &str = "\
+#include <stdbool.h>
+#include <stdint.h>
+struct point { int x, y; };
+int rust_sanity_probe(void) {
+    struct point p = { .y = 1 };
+    for (int i = 0; i < 2; i++) {
+        p.x += i;
+    }
+    // a line comment
+    bool ok = p.x == 1;
+    return ok ? 0 : (int)(uint64_t)p.y;
+}
+";
+
+fn check_c_standard(s: &mut Sanity) -> Result<(), SanityError> {
+    if !s.build.config.sanity_probes {
+        return Ok(())
+    }
+    let dir = probe_dir(s.build)?;
+    for target in s.build.targets.iter().filter(|t| !t.contains("emscripten")) {
+        let src = dir.join(format!("sanity-c99-{}.c", target));
+        write_probe_source(&src, C99_PROBE.as_bytes())?;
+        // The `cc` crate doesn't pass `-std`, so it's the compiler's default
+        // dialect which has to be new enough.
+        let cc = s.build.cc(*target);
+        let msvc = target.contains("msvc");
+        let syntax_check = |std: Option<&str>| {
+            let mut cmd = Command::new(cc);
+            if msvc {
+                cmd.arg("/nologo").arg("/Zs").arg("/TC");
+            } else {
+                cmd.args(&s.build.cflags(*target)).arg("-fsyntax-only");
+            }
+            cmd.args(std);
+            probe(cmd.arg(&src))
+        };
+        let out = syntax_check(None);
+        let with_std = if msvc { None } else { Some(syntax_check(Some("-std=gnu99"))) };
+        let _ = fs::remove_file(&src);
+        let out = match out {
+            Ok(out) => out,
+            // Not being able to run the compiler at all is reported elsewhere.
+            Err(_) => continue,
+        };
+        if out.status.success() {
+            continue
+        }
+        let errors = format!("{}{}", String::from_utf8_lossy(&out.stdout),
+                             String::from_utf8_lossy(&out.stderr));
+        let hint = match with_std {
+            Some(Ok(ref out)) if out.status.success() => {
+                "; it does with -std=gnu99, so its default dialect is older, which usually \
+                 means it's a very old compiler"
+            }
+            _ => "",
+        };
+        return Err(SanityError::new(ErrorKind::BadVersion,
+                                    format!("\n\nthe C compiler for {} ({}) can't compile C99, \
+                                             which the C code of the build needs{}\n\n{}\n\n",
+                                            target, cc.display(), hint, errors.trim()))
+            .about(target))
+    }
+    Ok(())
+}
+
+/// Expands to what kind of code a C compiler generates by default.
+const PIE_PROBE: &str = "#if defined(__PIE__)\n\
+                         rust_pie pie\n\
+                         #elif defined(__PIC__)\n\
+                         rust_pie pic\n\
+                         #else\n\
+                         rust_pie none\n\
+                         #endif\n";
+
+/// Finds what kind of code, `pie`, `pic` or `none`, the preprocessed
+/// `PIE_PROBE` says the compiler generates by default.
+fn default_code_model(preprocessed: &str) -> Option<&'static str> {
+    preprocessed.lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some("rust_pie"), Some("pie")) => Some("pie"),
+                (Some("rust_pie"), Some("pic")) => Some("pic"),
+                (Some("rust_pie"), Some("none")) => Some("none"),
+                _ => None,
+            }
+        })
+        .next()
+}
+
+fn check_pie_defaults(s: &mut Sanity) -> Result<(), SanityError> {
+    if !s.build.config.sanity_probes {
+        return Ok(())
+    }
+    let dir = probe_dir(s.build)?;
+    for target in &s.build.targets {
+        if target.contains("msvc") || target.contains("emscripten") || target.contains("wasm32") {
+            continue
+        }
+        // With the compiler doing the linking as well the two can't
+        // disagree.
+        let cc = s.build.cc(*target);
+        let linker = match s.build.linker(*target) {
+            Some(linker) if linker != cc => linker,
+            _ => continue,
+        };
+        let src = dir.join(format!("sanity-pie-{}.c", target));
+        write_probe_source(&src, PIE_PROBE.as_bytes())?;
+        let code_model = |driver: &Path| {
+            match probe(Command::new(driver).arg("-E").arg("-P").arg(&src)) {
+                Ok(ref out) if out.status.success() => {
+                    default_code_model(&String::from_utf8_lossy(&out.stdout))
+                }
+                _ => None,
+            }
+        };
+        // A GCC built with --enable-default-pie both defines __PIE__ and
+        // links with -pie. Linkers which aren't compiler drivers can't be
+        // asked like this.
+        let compiled = code_model(cc);
+        let linked = code_model(linker);
+        let _ = fs::remove_file(&src);
+        if compiled == Some("none") && linked == Some("pie") {
+            s.warnings.warn(format!("the C compiler for {} ({}) doesn't generate position \
+                                     independent code by default, but its linker ({}) makes \
+                                     position independent executables by default, so linking \
+                                     C code built without -fPIC will fail with relocation \
+                                     errors; use a compiler and linker from the same toolchain",
+                                    target, cc.display(), linker.display()));
+        }
+    }
+    Ok(())
+}
+
+fn check_cc_max_versions(s: &mut Sanity) -> Result<(), SanityError> {
+    for target in &s.build.targets {
+        if target.contains("emscripten") {
+            continue;
+        }
+        let max = s.build.config.target_config.get(target)
+            .and_then(|t| t.cc_max_version.as_ref());
+        if let Some(max) = max {
+            check_cc_max_version(s.build, *target, max, &mut s.warnings)?;
+        }
+    }
+    Ok(())
+}
+
+/// Compiler caches and distributors which are commonly installed as
+/// symlinks named after the compiler they wrap.
+const CACHING_SHIMS: &[&str] = &["ccache", "distcc", "sccache", "icecc"];
+
+/// Returns which of `CACHING_SHIMS` the compiler at `path` really is, if any.
+fn caching_shim(path: &Path) -> Option<&'static str> {
+    let real = fs::canonicalize(path).ok()?;
+    let name = real.file_stem()?.to_string_lossy().into_owned();
+    CACHING_SHIMS.iter().find(|shim| **shim == name).cloned()
+}
+
+fn check_implicit_compiler_caches(s: &mut Sanity) -> Result<(), SanityError> {
+    // With llvm.ccache set we know about the caching ourselves.
+    if s.build.config.ccache.is_some() {
+        return Ok(())
+    }
+    let build = &*s.build;
+    let mut compilers = Vec::new();
+    for target in build.targets.iter().filter(|t| !t.contains("emscripten")) {
+        compilers.push(("C", *target, build.cc(*target).to_path_buf()));
+    }
+    for host in &build.hosts {
+        if let Ok(cxx) = build.cxx(*host) {
+            compilers.push(("C++", *host, cxx.to_path_buf()));
+        }
+    }
+    for (lang, target, compiler) in compilers {
+        let compiler = resolve_bare_name(&mut s.finder, compiler);
+        if let Some(shim) = caching_shim(&compiler) {
+            s.warnings.note(format!("the {} compiler for {} ({}) is really {}, so builds \
+                                     are cached even though llvm.ccache isn't set",
+                                    lang, target, compiler.display(), shim));
+        }
+    }
+    Ok(())
+}
+
+fn check_linker_object_formats(s: &mut Sanity) -> Result<(), SanityError> {
+    for target in &s.build.targets {
+        if target.contains("emscripten") {
+            continue
+        }
+        let linker = match s.build.linker(*target) {
+            Some(linker) => linker,
+            None => continue,
+        };
+        let expected = object_format(target);
+        let supported = known_linker_format(linker).map(|f| f == expected).or_else(|| {
+            if s.build.config.sanity_probes {
+                probe_linker_formats(linker).map(|formats| formats.contains(&expected))
+            } else {
+                None
+            }
+        });
+        if supported == Some(false) {
+            s.warnings.warn(format!("the linker for {} ({}) doesn't appear to support {} \
+                                     objects, which that target uses",
+                                    target, linker.display(), expected));
+        }
+    }
+    Ok(())
+}
+
+/// Whether `target` is a wasm32 target linked by LLD rather than by emcc.
+fn links_with_wasm_lld(target: &str) -> bool {
+    target.starts_with("wasm32") && !target.contains("emscripten")
+}
+
+fn check_wasm_linkers(s: &mut Sanity) -> Result<(), SanityError> {
+    for target in &s.build.targets {
+        if !links_with_wasm_lld(target) {
+            continue
+        }
+        // A configured linker is checked by "tool-paths", and `rust.lld`
+        // builds rust-lld into the sysroot alongside LLVM.
+        if s.build.linker(*target).is_some() || s.build.config.lld_enabled {
+            continue
+        }
+        if s.finder.first_of(&["rust-lld", "wasm-ld", "lld"]).is_some() {
+            continue
+        }
+        let help = if building_llvm(s.build) {
+            "set `rust.lld = true` to build rust-lld along with LLVM, or install LLD \
+             (e.g. the `lld` package) so that `wasm-ld` is in PATH"
+        } else {
+            "LLVM is configured externally, so install the LLD matching it (e.g. the \
+             `lld` package) so that `wasm-ld` is in PATH, or point target.$TARGET.linker \
+             at one"
+        };
+        return Err(SanityError::new(ErrorKind::MissingTool,
+                                    format!("{} links with LLD, but rust.lld is off and \
+                                             neither rust-lld, wasm-ld nor lld is in \
+                                             PATH; {}",
+                                            target, help.replace("$TARGET", target)))
+            .about(target))
+    }
+    Ok(())
+}
+
+/// The linkers `cc` drivers are commonly set up to use.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum LinkerFlavor {
+    Bfd,
+    Gold,
+    Lld,
+    Mold,
+}
+
+/// Works out which linker printed `version`, the output of `--version`.
+fn linker_flavor(version: &str) -> Option<LinkerFlavor> {
+    let first = version.lines().next().unwrap_or("");
+    if first.starts_with("GNU ld") {
+        Some(LinkerFlavor::Bfd)
+    } else if first.starts_with("GNU gold") {
+        Some(LinkerFlavor::Gold)
+    } else if first.starts_with("LLD") || first.contains(" LLD ") {
+        Some(LinkerFlavor::Lld)
+    } else if first.starts_with("mold") {
+        Some(LinkerFlavor::Mold)
+    } else {
+        None
+    }
+}
+
+fn check_host_linker_speed(s: &mut Sanity) -> Result<(), SanityError> {
+    // Most contributors link with GNU ld, so this is only worth a process
+    // and a note when asked for.
+    if !s.build.config.sanity_probes {
+        return Ok(())
+    }
+    for host in &s.build.hosts {
+        // Only ELF platforms get to choose, and they link through `cc`.
+        if host.contains("windows") || host.contains("apple") {
+            continue
+        }
+        let linker = s.build.linker(*host).unwrap_or_else(|| s.build.cc(*host));
+        let out = match probe(Command::new(linker).arg("-Wl,--version")) {
+            Ok(ref out) if out.status.success() => out.stdout.clone(),
+            _ => continue,
+        };
+        if linker_flavor(&String::from_utf8_lossy(&out)) == Some(LinkerFlavor::Bfd) {
+            s.warnings.note(format!("{} ({}) links with GNU ld, which only uses a single \
+                                     thread; installing lld, gold or mold and making {} use \
+                                     one of them makes linking the compiler much faster",
+                                    host, linker.display(), linker.display()));
+        }
+    }
+    Ok(())
+}
+
+/// Returns whether code for `target` has 32-bit pointers, judging by the
+/// architecture.
+fn is_32bit(target: &str) -> bool {
+    let arch = target.split('-').next().unwrap_or("");
+    !(arch.contains("64") || arch == "s390x" || arch == "sparcv9")
+}
+
+fn check_debug_link_size(s: &mut Sanity) -> Result<(), SanityError> {
+    let build = &*s.build;
+    let config = &build.config;
+    // The linkers run on the build machine, so that's where the address
+    // space runs out.
+    if !is_32bit(&build.build) {
+        return Ok(())
+    }
+    let mut big = Vec::new();
+    if config.rust_debuginfo && !config.rust_debuginfo_only_std {
+        big.push("the compiler with rust.debuginfo (set rust.debuginfo-only-std to avoid it)");
+    }
+    if config.llvm_release_debuginfo && building_llvm(build) {
+        big.push("LLVM with llvm.release-debuginfo");
+    }
+    if big.is_empty() {
+        return Ok(())
+    }
+    let mut msg = format!("{} is building {}, and linking those with debuginfo can exhaust \
+                           the address space of a 32-bit linker; a 64-bit build machine or \
+                           toolchain avoids that",
+                          build.build, big.join(" and "));
+    if !config.dry_run && !build.build.contains("windows") && !build.build.contains("apple") {
+        let linker = build.linker(build.build).unwrap_or_else(|| build.cc(build.build));
+        if let Ok(out) = probe(Command::new(linker).arg("-Wl,--version")) {
+            if out.status.success() &&
+               linker_flavor(&String::from_utf8_lossy(&out.stdout)) == Some(LinkerFlavor::Bfd) {
+                msg.push_str(&format!(", and {} links with GNU ld, which needs much more \
+                                       memory than gold or lld", linker.display()));
+            }
+        }
+    }
+    s.warnings.warn(msg);
+    Ok(())
+}
+
+fn check_cxx_compilers(s: &mut Sanity) -> Result<(), SanityError> {
+    for host in &s.build.hosts {
+        s.finder.require(s.build.cxx(*host).unwrap())?;
+    }
+    Ok(())
+}
+
+/// Standard headers the C++ shims for LLVM include, which are missing
+/// surprisingly often on minimal images that come with just `g++`.
+const CXX_PROBE_HEADERS: &[&str] = &["memory", "string", "vector", "atomic", "mutex"];
+
+/// Returns which of `CXX_PROBE_HEADERS` the compiler `errors` complain about.
+fn missing_header(errors: &str) -> Option<&'static str> {
+    // GCC says `memory: No such file`, clang and MSVC quote it as 'memory'.
+    CXX_PROBE_HEADERS.iter()
+        .find(|header| errors.contains(&format!("{}:", header)) ||
+                       errors.contains(&format!("'{}'", header)))
+        .cloned()
+}
+
+fn check_cxx_headers(s: &mut Sanity) -> Result<(), SanityError> {
+    if !s.build.config.sanity_probes {
+        return Ok(())
+    }
+    let dir = probe_dir(s.build)?;
+    for host in &s.build.hosts {
+        let cxx = s.build.cxx(*host).unwrap();
+        let src = dir.join(format!("sanity-cxx-headers-{}.cpp", host));
+        let program = CXX_PROBE_HEADERS.iter()
+            .map(|header| format!("#include <{}>\n", header))
+            .collect::<String>();
+        write_probe_source(&src, program.as_bytes())?;
+        // Only check the syntax so there's nothing else to clean up.
+        let mut cmd = Command::new(cxx);
+        if host.contains("msvc") {
+            cmd.arg("/nologo").arg("/Zs").arg("/EHsc");
+        } else {
+            cmd.arg("-fsyntax-only");
+        }
+        let out = probe(cmd.arg(&src));
+        let _ = fs::remove_file(&src);
+        let out = match out {
+            Ok(out) => out,
+            // Not being able to run the compiler at all is reported elsewhere.
+            Err(_) => continue,
+        };
+        if out.status.success() {
+            continue
+        }
+        // MSVC reports errors on stdout.
+        let errors = format!("{}{}", String::from_utf8_lossy(&out.stdout),
+                             String::from_utf8_lossy(&out.stderr));
+        let failed = missing_header(&errors)
+            .map_or("a standard header".to_string(), |header| format!("<{}>", header));
+        return Err(SanityError::new(ErrorKind::Environment,
+                                    format!("\n\nthe C++ compiler for {} ({}) couldn't \
+                                             include {}, are the C++ standard library \
+                                             headers installed?\n\n{}\n\n",
+                                            host, cxx.display(), failed, errors.trim()))
+            .about(host))
+    }
+    Ok(())
+}
+
+/// Reads the DWARF version of the first unit in the `.debug_info` section of
+/// `elf`, the contents of an ELF object file.
+fn dwarf_version(elf: &[u8]) -> Option<u16> {
+    if elf.len() < 64 || &elf[..4] != b"\x7fELF" {
+        return None
+    }
+    let (wide, big) = (elf[4] == 2, elf[5] == 2);
+    let read = |offset: usize, len: usize| -> Option<u64> {
+        let bytes = elf.get(offset..offset.checked_add(len)?)?;
+        let mut n = 0;
+        for i in 0..len {
+            let byte = if big { bytes[i] } else { bytes[len - 1 - i] };
+            n = n << 8 | byte as u64;
+        }
+        Some(n)
+    };
+    // The section header table, and the size of its fields.
+    let (shoff, shentsize, shnum, shstrndx, word) = if wide {
+        (read(0x28, 8)?, read(0x3a, 2)?, read(0x3c, 2)?, read(0x3e, 2)?, 8)
+    } else {
+        (read(0x20, 4)?, read(0x2e, 2)?, read(0x30, 2)?, read(0x32, 2)?, 4)
+    };
+    let section = |i: u64| -> Option<(u64, u64, u64, u64)> {
+        // The header may come from anywhere, so none of this may overflow.
+        let base = i.checked_mul(shentsize)?.checked_add(shoff)? as usize;
+        // sh_name, sh_flags, sh_offset and sh_size.
+        Some((read(base, 4)?, read(base.checked_add(8)?, word)?,
+              read(base.checked_add(8 + 2 * word)?, word)?,
+              read(base.checked_add(8 + 3 * word)?, word)?))
+    };
+    let (_, _, strtab, strtab_size) = section(shstrndx)?;
+    let names = elf.get(strtab as usize..strtab.checked_add(strtab_size)? as usize)?;
+    for i in 0..shnum {
+        let (name, flags, offset, size) = section(i)?;
+        let name = names.get(name as usize..)?;
+        if !name.starts_with(b".debug_info\0") {
+            continue
+        }
+        // SHF_COMPRESSED sections start with a compression header instead.
+        if flags & 0x800 != 0 {
+            return None
+        }
+        // 64-bit DWARF marks its longer unit length with 0xffffffff.
+        let version = match read(offset as usize, 4)? {
+            0xffff_ffff => offset.checked_add(12)?,
+            _ => offset.checked_add(4)?,
+        };
+        if version.checked_add(2)? > offset.checked_add(size)? {
+            return None
+        }
+        return read(version as usize, 2).map(|v| v as u16)
+    }
+    None
+}
+
+fn check_debuginfo_formats(s: &mut Sanity) -> Result<(), SanityError> {
+    if !s.build.config.sanity_probes {
+        return Ok(())
+    }
+    let dir = probe_dir(s.build)?;
+    for host in &s.build.hosts {
+        // MSVC's CodeView and Mach-O objects aren't looked into.
+        if target_elf_machine(host).is_none() {
+            continue
+        }
+        // Both end up in the compiler, through LLVM and its C++ shims.
+        let compilers = [
+            ("C", s.build.cc(*host).to_path_buf(), "c"),
+            ("C++", s.build.cxx(*host).unwrap().to_path_buf(), "cpp"),
+        ];
+        let mut versions = Vec::new();
+        for &(lang, ref compiler, ext) in &compilers {
+            let src = dir.join(format!("sanity-dwarf-{}.{}", host, ext));
+            let obj = dir.join(format!("sanity-dwarf-{}-{}.o", host, ext));
+            write_probe_source(&src, b"int rust_sanity_probe(int x) { return x; }\n")?;
+            let out = probe(Command::new(compiler).args(&s.build.cflags(*host))
+                                                  .arg("-g").arg("-c").arg(&src)
+                                                  .arg("-o").arg(&obj));
+            let mut contents = Vec::new();
+            let read = File::open(&obj).and_then(|mut f| f.read_to_end(&mut contents));
+            let _ = fs::remove_file(&src);
+            let _ = fs::remove_file(&obj);
+            // Compilers which can't build this are reported elsewhere.
+            match out {
+                Ok(ref out) if out.status.success() && read.is_ok() => {}
+                _ => continue,
+            }
+            if let Some(version) = dwarf_version(&contents) {
+                versions.push((lang, compiler.clone(), version));
+            }
+        }
+        if versions.len() == 2 && versions[0].2 != versions[1].2 {
+            s.warnings.warn(format!("the {} compiler for {} ({}) emits DWARF {}, but the {} \
+                                     compiler ({}) emits DWARF {}, and they're linked together; \
+                                     debuggers which only understand one of them won't make \
+                                     sense of the whole",
+                                    versions[0].0, host, versions[0].1.display(), versions[0].2,
+                                    versions[1].0, versions[1].1.display(), versions[1].2));
+        }
+    }
+    Ok(())
+}
+
+fn check_llvm_cmake_caches(s: &mut Sanity) -> Result<(), SanityError> {
+    for host in &s.build.hosts {
+        let building = s.build.config.target_config.get(host)
+            .map_or(true, |config| config.llvm_config.is_none());
+        if !building {
+            continue
+        }
+        let cache = s.build.llvm_out(*host).join("build/CMakeCache.txt");
+        let mut contents = String::new();
+        if File::open(&cache).and_then(|mut f| f.read_to_string(&mut contents)).is_err() {
+            continue
+        }
+        // cmake refuses to reuse a cache for sources elsewhere, which happens
+        // when the checkout moves but the build directory stays.
+        let sources = s.build.src.join("src/llvm");
+        if let Some(cached) = cmake_cache_entry(&contents, "CMAKE_HOME_DIRECTORY") {
+            if !same_dir(Path::new(cached), &sources) {
+                s.warnings.warn(format!("LLVM for {} was configured with its sources in {}, \
+                                         but they're in {} now, which cmake will refuse; \
+                                         remove {} for a clean rebuild",
+                                        host, cached, sources.display(),
+                                        s.build.llvm_out(*host).display()));
+                continue
+            }
+        }
+        // The MSVC builds go through wrappers we can't see through.
+        if host.contains("msvc") {
+            continue
+        }
+        // With ccache the compiler is passed as its first argument, see
+        // `configure_cmake` in native.rs.
+        let key = if s.build.config.ccache.is_some() {
+            "CMAKE_C_COMPILER_ARG1"
+        } else {
+            "CMAKE_C_COMPILER"
+        };
+        let cached = match cmake_cache_entry(&contents, key) {
+            Some(cached) => resolve_bare_name(&mut s.finder, PathBuf::from(cached)),
+            None => continue,
+        };
+        let current = resolve_bare_name(&mut s.finder, s.build.cc(*host).to_path_buf());
+        if cached != current {
+            s.warnings.warn(format!("LLVM for {} was configured with {} as its C compiler, but \
+                                     {} is used now; remove {} for a clean rebuild",
+                                    host, cached.display(), current.display(),
+                                    s.build.llvm_out(*host).display()));
+        }
+    }
+    Ok(())
+}
+
+/// Returns whether `a` and `b` are the same existing directory, however
+/// they're spelled.
+fn same_dir(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Returns the value of `key` in the contents of a `CMakeCache.txt`, which
+/// has lines like `CMAKE_C_COMPILER:FILEPATH=/usr/bin/cc`.
+fn cmake_cache_entry<'a>(contents: &'a str, key: &str) -> Option<&'a str> {
+    contents.lines()
+        .filter(|line| !line.starts_with('#') && !line.starts_with("//"))
+        .filter_map(|line| {
+            let eq = line.find('=')?;
+            let (name, value) = (&line[..eq], &line[eq + 1..]);
+            let name = name.split(':').next().unwrap_or(name);
+            if name == key { Some(value.trim()) } else { None }
+        })
+        .next()
+}
+
+fn check_static_hosts(s: &mut Sanity) -> Result<(), SanityError> {
+    for host in &s.build.hosts {
+        // Fully static hosts need static versions of the C++ runtime
+        // libraries to link LLVM and the compiler, and their absence is
+        // otherwise only discovered by the very last link.
+        let static_host = host.contains("musl") ||
+            s.build.config.llvm_static_stdcpp ||
+            s.build.crt_static(*host) == Some(true);
+        if static_host && !host.contains("windows") && !host.contains("apple") {
+            check_static_host_libs(s.build, *host)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the environment part of `target`, which names its C runtime, like
+/// the `gnu` of `x86_64-unknown-linux-gnu`. It's empty for targets which
+/// don't have one, like `x86_64-apple-darwin`.
+fn target_env(target: &str) -> &str {
+    let parts = target.split('-').collect::<Vec<_>>();
+    if parts.len() < 4 {
+        return ""
+    }
+    parts[3]
+}
+
+/// Returns whether the compiler for `host` can't load proc-macros, which are
+/// dynamic libraries built for the host, given its `crt-static` setting.
+fn proc_macros_unsupported(host: &str, crt_static: Option<bool>) -> bool {
+    // musl links statically unless told otherwise, and a static executable
+    // can't `dlopen` anything.
+    match crt_static {
+        Some(crt_static) => crt_static,
+        None => target_env(host).starts_with("musl"),
+    }
+}
+
+fn check_proc_macro_hosts(s: &mut Sanity) -> Result<(), SanityError> {
+    for host in &s.build.hosts {
+        if !proc_macros_unsupported(host, s.build.crt_static(*host)) {
+            continue
+        }
+        let targets = s.build.targets.iter()
+            .map(|target| match target_env(target) {
+                "" => target.to_string(),
+                env => format!("{} ({})", target, env),
+            })
+            .collect::<Vec<_>>();
+        s.warnings.warn(format!("the compiler for {} ({}) will be linked statically, so it \
+                                 can't load the proc-macros that crates for {} may use; set \
+                                 target.{}.crt-static = false if they do",
+                                host, target_env(host), targets.join(", "), host));
+    }
+    Ok(())
+}
+
+/// Settings of `crt-static` which don't do what they say for targets whose
+/// name contains the pattern, and why.
+///
+/// Only musl and MSVC targets let the C runtime be linked either way, and
+/// everywhere else rustc quietly ignores `+crt-static`.
+const CRT_STATIC_CONFLICTS: &[(&str, bool, &str)] = &[
+    ("-pc-windows-msvc", false, "the build always links the MSVC runtime statically"),
+    ("-pc-windows-gnu", true, "rustc doesn't support linking the MinGW runtime statically"),
+    ("-linux-gnu", true, "rustc doesn't support linking glibc statically"),
+    ("-linux-android", true, "Android has no static C runtime"),
+    ("-apple-", true, "Apple's platforms have no static C runtime"),
+];
+
+/// Returns why setting `crt-static` to `crt_static` for `target` won't work,
+/// if it won't.
+fn crt_static_conflict(target: &str, crt_static: bool) -> Option<&'static str> {
+    CRT_STATIC_CONFLICTS.iter()
+        .find(|&&(pattern, setting, _)| target.contains(pattern) && setting == crt_static)
+        .map(|&(_, _, why)| why)
+}
+
+fn check_crt_static(s: &mut Sanity) -> Result<(), SanityError> {
+    let mut targets = s.build.config.target_config.iter().collect::<Vec<_>>();
+    targets.sort_by_key(|&(target, _)| target);
+    for (target, config) in targets {
+        let crt_static = match config.crt_static {
+            Some(crt_static) => crt_static,
+            None => continue,
+        };
+        if let Some(why) = crt_static_conflict(target, crt_static) {
+            s.warnings.warn(format!("target.{}.crt-static is set to {}, but {}, so the setting \
+                                     is ignored and linking may not work the way it expects",
+                                    target, crt_static, why));
+        }
+    }
+    Ok(())
+}
+
+fn check_sanitizers(s: &mut Sanity) -> Result<(), SanityError> {
+    // A compiler which can instrument code for a sanitizer isn't much use
+    // without the matching runtime library, so look for those as well.
+    if s.build.config.sanitizers {
+        for target in &s.build.targets {
+            check_sanitizer_runtimes(s.build, *target, &mut s.warnings);
+        }
+    }
+    Ok(())
+}
+
+/// Environment variables which tools run during the build consult on their
+/// own, possibly picking something else than what was configured.
+const OVERRIDE_VARS: &[&str] = &["CC", "CXX", "AR", "CMAKE", "PYTHON"];
+
+/// Returns what the build itself uses in place of the environment variable
+/// `var`, if anything.
+fn overridden_value(s: &mut Sanity, var: &str) -> Option<PathBuf> {
+    let build = s.build.build;
+    match var {
+        "CC" => Some(s.build.cc(build).to_path_buf()),
+        "CXX" => s.build.cxx(build).ok().map(|p| p.to_path_buf()),
+        "AR" => s.build.ar(build).map(|p| p.to_path_buf()),
+        "CMAKE" => s.finder.maybe_have("cmake"),
+        "PYTHON" => s.build.config.python.clone(),
+        _ => None,
+    }
+}
+
+/// Looks `path` up in `PATH` if it's a bare name like `gcc`, so it can be
+/// compared with full paths.
+fn resolve_bare_name(finder: &mut Finder, path: PathBuf) -> PathBuf {
+    if path.components().count() == 1 {
+        finder.maybe_have(&path).unwrap_or(path)
+    } else {
+        path
+    }
+}
+
+fn check_env_overrides(s: &mut Sanity) -> Result<(), SanityError> {
+    for var in OVERRIDE_VARS {
+        let value = match env::var_os(var) {
+            Some(ref value) if !value.is_empty() => value.clone(),
+            _ => continue,
+        };
+        let ours = match overridden_value(s, var) {
+            Some(ours) => resolve_bare_name(&mut s.finder, ours),
+            None => continue,
+        };
+        let theirs = resolve_bare_name(&mut s.finder, PathBuf::from(value));
+        if theirs != ours {
+            s.warnings.warn(format!("{} is set to {} in the environment, which is what \
+                                     some tools will use instead of {}",
+                                    var, theirs.display(), ours.display()));
+        }
+    }
+    check_global_compilers(s);
+    Ok(())
+}
+
+/// Returns which of `targets` take their compiler from the global `var`,
+/// like `CC`, because they have neither one configured nor a per-target
+/// variable like `CC_x86_64-unknown-linux-gnu` set in `env`.
+fn global_compiler_targets(var: &str,
+                           targets: &[Interned<String>],
+                           configured: &Fn(Interned<String>) -> bool,
+                           env: &Fn(&str) -> Option<OsString>)
+                           -> Vec<Interned<String>> {
+    targets.iter().cloned().filter(|target| {
+        // These are the names the `cc` crate looks for.
+        let per_target = [format!("{}_{}", var, target),
+                          format!("{}_{}", var, target.replace('-', "_"))];
+        !configured(*target) && !per_target.iter().any(|name| env(name).is_some())
+    }).collect()
+}
+
+/// Warns when a global `CC` or `CXX` is the compiler of several targets,
+/// which is hardly ever what's wanted when cross compiling.
+fn check_global_compilers(s: &mut Sanity) {
+    let mut targets = s.build.targets.iter().chain(&s.build.hosts).cloned()
+        .chain(Some(s.build.build))
+        .collect::<Vec<_>>();
+    targets.sort();
+    targets.dedup();
+    let mut hosts = s.build.hosts.iter().cloned().chain(Some(s.build.build)).collect::<Vec<_>>();
+    hosts.sort();
+    hosts.dedup();
+
+    let target_config = &s.build.config.target_config;
+    let has_cc = |target: Interned<String>| {
+        target_config.get(&target).map_or(false, |t| t.cc.is_some())
+    };
+    let has_cxx = |target: Interned<String>| {
+        target_config.get(&target).map_or(false, |t| t.cxx.is_some())
+    };
+    let vars: [(&str, &[Interned<String>], &Fn(Interned<String>) -> bool, &str); 2] = [
+        ("CC", &targets, &has_cc, "cc"),
+        ("CXX", &hosts, &has_cxx, "cxx"),
+    ];
+    for &(var, targets, configured, key) in vars.iter() {
+        if env::var_os(var).map_or(true, |value| value.is_empty()) {
+            continue
+        }
+        let affected = global_compiler_targets(var, targets, configured,
+                                               &|name| env::var_os(name));
+        if affected.len() > 1 {
+            let affected = affected.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+            s.warnings.warn(format!("{} is set in the environment, so {} all use it as their \
+                                     compiler; set target.$TARGET.{} in config.toml for each \
+                                     of them instead",
+                                    var, affected.join(", "), key));
+        }
+    }
+}
+
+fn check_jemalloc_hosts(s: &mut Sanity) -> Result<(), SanityError> {
+    if !s.build.config.use_jemalloc {
+        return Ok(())
+    }
+    let hosts = s.build.hosts.iter().map(|host| &host[..]).collect::<Vec<_>>();
+    let (with, without) = jemalloc_hosts(&hosts);
+    // With a single kind of host it's obvious what the setting does.
+    if !with.is_empty() && !without.is_empty() {
+        s.warnings.note(format!("rust.use-jemalloc only applies to {}, the compilers and \
+                                 standard libraries for {} are built without jemalloc",
+                                with.join(", "), without.join(", ")));
+    }
+    Ok(())
+}
+
+fn check_filecheck(s: &mut Sanity) -> Result<(), SanityError> {
+    // Externally configured LLVM requires FileCheck to exist
+    let build = &s.build;
+    let filecheck = build.llvm_filecheck(build.build);
+    if !filecheck.starts_with(&build.out) && !filecheck.exists() && build.config.codegen_tests {
+        return Err(SanityError::new(ErrorKind::MissingTool,
+                                    format!("FileCheck executable {:?} does not exist",
+                                            filecheck)).about(&filecheck))
+    }
+    Ok(())
+}
+
+fn check_llvm_assertions_tests(s: &mut Sanity) -> Result<(), SanityError> {
+    let config = &s.build.config;
+    // Assertions in an external LLVM aren't ours to build.
+    if !config.llvm_assertions || config.codegen_tests || !building_llvm(s.build) {
+        return Ok(())
+    }
+    s.warnings.note("llvm.assertions is enabled but rust.codegen-tests is disabled, so the \
+                     slower LLVM build won't be exercised by the tests that rely on it most; \
+                     enable rust.codegen-tests or disable llvm.assertions to save time"
+                    .to_string());
+    Ok(())
+}
+
+fn check_ios_host(s: &mut Sanity) -> Result<(), SanityError> {
+    for target in &s.build.targets {
+        // Can't compile for iOS unless we're on macOS
+        if target.contains("apple-ios") &&
+           !s.build.build.contains("apple-darwin") {
+            return Err(SanityError::new(ErrorKind::Config,
+                                        "the iOS target is only supported on macOS".to_string())
+                .about(target))
+        }
+    }
+    Ok(())
+}
+
+/// Returns the SDK `target` is built against and the oldest version of it
+/// which supports the target's default deployment target.
+fn apple_sdk(target: &str) -> Option<(&'static str, (u64, u64, u64))> {
+    if target.ends_with("-apple-darwin") {
+        Some(("macosx", (10, 7, 0)))
+    } else if !target.ends_with("-apple-ios") {
+        None
+    } else if target.starts_with("i386-") || target.starts_with("x86_64-") {
+        Some(("iphonesimulator", (7, 0, 0)))
+    } else {
+        Some(("iphoneos", (7, 0, 0)))
+    }
+}
+
+/// Returns the oldest Android API level the standard library supports on
+/// `target`. For 64-bit targets that's 21, the first level to support them.
+fn android_min_api(target: &str) -> Option<u32> {
+    if !target.contains("android") {
+        None
+    } else if target.starts_with("aarch64-") || target.starts_with("x86_64-") {
+        Some(21)
+    } else {
+        Some(14)
+    }
+}
+
+/// Finds the API level in the preprocessed `ANDROID_API_PROBE`.
+fn android_api_level(preprocessed: &str) -> Option<u32> {
+    preprocessed.lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some("rust_android_api"), Some(level)) => level.parse().ok(),
+                _ => None,
+            }
+        })
+        .next()
+}
+
+/// Expands to the API level a standalone NDK toolchain was made for with
+/// `--api`.
+const ANDROID_API_PROBE: &str = "#include <android/api-level.h>\n\
+                                 rust_android_api __ANDROID_API__\n";
+
+fn check_android_api_levels(s: &mut Sanity) -> Result<(), SanityError> {
+    for target in &s.build.targets {
+        let required = match android_min_api(target) {
+            Some(required) => required,
+            None => continue,
+        };
+        let dir = probe_dir(s.build)?;
+        let src = dir.join(format!("sanity-android-api-{}.c", target));
+        write_probe_source(&src, ANDROID_API_PROBE.as_bytes())?;
+        let cc = s.build.cc(*target);
+        let out = probe(Command::new(cc).arg("-E").arg("-P").arg(&src));
+        let _ = fs::remove_file(&src);
+        // If the compiler can't preprocess this it isn't a standalone
+        // toolchain we can ask, and problems running it are reported
+        // elsewhere.
+        let level = match out {
+            Ok(ref out) if out.status.success() => {
+                android_api_level(&String::from_utf8_lossy(&out.stdout))
+            }
+            _ => None,
+        };
+        let level = match level {
+            Some(level) => level,
+            None => continue,
+        };
+        if level < required {
+            return Err(SanityError::new(ErrorKind::BadVersion,
+                                        format!("the Android toolchain for {} ({}) targets \
+                                                 API level {}, but {} needs at least API \
+                                                 level {}; make the standalone toolchain \
+                                                 with `--api {}` or newer",
+                                                target, cc.display(), level, target, required,
+                                                required))
+                       .about(cc))
+        }
+    }
+    Ok(())
+}
+
+fn check_apple_sdks(s: &mut Sanity) -> Result<(), SanityError> {
+    if !s.build.build.contains("apple-darwin") {
+        return Ok(())
+    }
+    for target in &s.build.targets {
+        let (sdk, required) = match apple_sdk(target) {
+            Some(sdk) => sdk,
+            None => continue,
+        };
+        let out = probe(Command::new("xcrun").arg("--sdk").arg(sdk).arg("--show-sdk-version"))
+            .ok()
+            .and_then(|out| if out.status.success() { Some(out) } else { None });
+        let version = out.as_ref()
+            .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string());
+        let installed = match version.as_ref().and_then(|v| parse_version(v)) {
+            Some(installed) => installed,
+            // The command line tools alone are enough for macOS, but iOS
+            // needs a full Xcode.
+            None if sdk == "macosx" => continue,
+            None => {
+                return Err(SanityError::new(ErrorKind::MissingTool,
+                                            format!("couldn't find the {} SDK needed for {}, \
+                                                     is Xcode installed?", sdk, target))
+                    .about(target))
+            }
+        };
+        if installed < required {
+            return Err(SanityError::new(ErrorKind::BadVersion,
+                                        format!("the installed {} SDK is version {}, but {} \
+                                                 needs at least {}.{}",
+                                                sdk, version.unwrap(), target,
+                                                required.0, required.1))
+                .about(target))
+        }
+    }
+    Ok(())
+}
+
+fn check_no_std_targets(s: &mut Sanity) -> Result<(), SanityError> {
+    for target in &s.build.targets {
+        if target.contains("-none-") && s.build.no_std(*target) == Some(false) {
+            return Err(SanityError::new(ErrorKind::Config,
+                                        "All the *-none-* targets are no-std targets".to_string())
+                .about(target))
+        }
+    }
+    Ok(())
+}
+
+fn check_musl_root(s: &mut Sanity) -> Result<(), SanityError> {
+    for target in &s.build.targets {
+        // Make sure musl-root is valid
+        if !target.contains("musl") {
+            continue
+        }
+        match s.build.musl_root(*target) {
+            Some(root) => {
+                if let Some(archive) = missing_musl_archive(&root.join("lib")) {
+                    return Err(SanityError::new(ErrorKind::Environment,
+                                                format!("couldn't find {} in musl dir: {}",
+                                                        archive, root.join("lib").display()))
+                        .about(target))
+                }
+                // Without the startup objects only libraries can be linked,
+                // so this otherwise isn't noticed until the first executable.
+                for obj in MUSL_CRT_OBJECTS {
+                    if find_crt_object(&root.join("lib"), target, obj).is_none() {
+                        return Err(SanityError::new(ErrorKind::Environment,
+                                                    format!("couldn't find {} in musl dir: {}",
+                                                            obj, root.join("lib").display()))
+                            .about(target))
+                    }
+                }
+            }
+            None => {
+                return Err(SanityError::new(ErrorKind::Config,
+                                            "when targeting MUSL either the rust.musl-root \
+                                             option or the target.$TARGET.musl-root option \
+                                             must be specified in config.toml".to_string())
+                    .about(target))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the first archive every musl root needs which is missing from
+/// `lib`, the root's `lib` directory.
+fn missing_musl_archive(lib: &Path) -> Option<&'static str> {
+    ["libc.a", "libunwind.a"].iter()
+        .find(|archive| fs::metadata(lib.join(archive)).is_err())
+        .cloned()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Endian {
+    Little,
+    Big,
+}
+
+impl fmt::Display for Endian {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Endian::Little => "little-endian",
+            Endian::Big => "big-endian",
+        })
+    }
+}
+
+/// Returns the byte order of `target`, going by its architecture.
+fn target_endian(target: &str) -> Endian {
+    let arch = target.split('-').next().unwrap_or("");
+    let big = match arch {
+        "powerpc" | "powerpc64" | "mips" | "mips64" | "mipsisa32r6" | "mipsisa64r6" |
+        "sparc" | "sparc64" | "sparcv9" | "s390x" | "aarch64_be" => true,
+        _ => arch.starts_with("armeb") || arch.starts_with("thumbeb"),
+    };
+    if big { Endian::Big } else { Endian::Little }
+}
+
+/// Reads the byte order out of the header of the ELF file at `path`,
+/// returning `None` if it isn't one.
+fn elf_endian(path: &Path) -> Option<Endian> {
+    let mut header = [0; 6];
+    File::open(path).ok()?.read_exact(&mut header).ok()?;
+    if &header[..4] != b"\x7fELF" {
+        return None
+    }
+    // EI_DATA
+    match header[5] {
+        1 => Some(Endian::Little),
+        2 => Some(Endian::Big),
+        _ => None,
+    }
+}
+
+/// Reads the `e_machine` of the first ELF object in the `ar` archive at
+/// `path`, which skips the symbol and long name tables in front of them.
+fn archive_elf_machine(path: &Path) -> Option<u16> {
+    let mut file = File::open(path).ok()?;
+    let mut magic = [0; 8];
+    file.read_exact(&mut magic).ok()?;
+    if &magic != b"!<arch>\n" {
+        return None
+    }
+    loop {
+        let mut header = [0; 60];
+        file.read_exact(&mut header).ok()?;
+        let size = String::from_utf8_lossy(&header[48..58]).trim().parse::<u64>().ok()?;
+        let mut start = Vec::new();
+        (&mut file).take(cmp::min(size, 20)).read_to_end(&mut start).ok()?;
+        if let Some(machine) = elf_machine(&start) {
+            return Some(machine)
+        }
+        // Members are padded to an even size.
+        let rest = size + size % 2 - start.len() as u64;
+        file.seek(SeekFrom::Current(rest as i64)).ok()?;
+    }
+}
+
+fn check_musl_root_arch(s: &mut Sanity) -> Result<(), SanityError> {
+    for target in &s.build.targets {
+        if !target.contains("musl") {
+            continue
+        }
+        let expected = match target_elf_machine(target) {
+            Some(expected) => expected,
+            None => continue,
+        };
+        let lib = match s.build.musl_root(*target) {
+            Some(root) => root.join("lib"),
+            None => continue,
+        };
+        // The startup objects are looked at too, as they're found separately
+        // and can come from another directory.
+        let mut files = vec![lib.join("libc.a"), lib.join("libunwind.a")];
+        files.extend(find_crt_object(&lib, target, "crt1.o"));
+        for file in files {
+            let found = if file.extension().map_or(false, |ext| ext == "a") {
+                archive_elf_machine(&file)
+            } else {
+                let mut header = Vec::new();
+                File::open(&file).and_then(|f| f.take(20).read_to_end(&mut header)).ok()
+                    .and_then(|_| elf_machine(&header))
+            };
+            match found {
+                Some(found) if found != expected => {
+                    return Err(SanityError::new(ErrorKind::Environment,
+                                                format!("{} is for {} rather than {}, which {} \
+                                                         needs; is the musl-root for another \
+                                                         architecture?",
+                                                        file.display(), elf_machine_name(found),
+                                                        elf_machine_name(expected), target))
+                        .about(target))
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_musl_root_endianness(s: &mut Sanity) -> Result<(), SanityError> {
+    for target in &s.build.targets {
+        if !target.contains("musl") {
+            continue
+        }
+        let lib = match s.build.musl_root(*target) {
+            Some(root) => root.join("lib"),
+            None => continue,
+        };
+        // `libc.a` is an archive rather than an ELF file, but the startup
+        // objects are just objects.
+        let obj = match find_crt_object(&lib, target, "crt1.o") {
+            Some(obj) => obj,
+            None => continue,
+        };
+        let expected = target_endian(target);
+        match elf_endian(&obj) {
+            Some(found) if found != expected => {
+                s.warnings.warn(format!("{} is {}, but {} is {}, is the musl-root for \
+                                         another architecture?",
+                                        obj.display(), found, target, expected));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// An OS whose targets are built against an SDK, set with `target.$TARGET.sdk`.
+struct SdkTarget {
+    /// The OS component of target triples built against this SDK.
+    os: &'static str,
+    name: &'static str,
+    /// The environment variable the SDK's own tooling finds it with, if it
+    /// has one.
+    env: Option<&'static str>,
+    /// How the SDK names architectures, where that differs from the triple.
+    arches: &'static [(&'static str, &'static str)],
+    /// Paths the SDK has to contain, where `{arch}` is the SDK's name for
+    /// the target's architecture.
+    required: &'static [&'static str],
+    help: &'static str,
+}
+
+static SDK_TARGETS: &[SdkTarget] = &[
+    SdkTarget {
+        os: "fuchsia",
+        name: "Fuchsia",
+        env: Some("FUCHSIA_SDK"),
+        arches: &[("x86_64", "x64"), ("aarch64", "arm64")],
+        required: &["arch/{arch}/sysroot/include", "arch/{arch}/sysroot/lib", "tools"],
+        help: "point target.$TARGET.sdk or FUCHSIA_SDK at an SDK built from \
+               a Fuchsia checkout",
+    },
+    SdkTarget {
+        os: "haiku",
+        name: "Haiku",
+        env: None,
+        arches: &[],
+        required: &["boot/system/develop/headers/posix", "boot/system/develop/lib"],
+        help: "point target.$TARGET.sdk at the sysroot of the cross tools built by Haiku's \
+               `configure --build-cross-tools`",
+    },
+    SdkTarget {
+        os: "redox",
+        name: "Redox",
+        env: None,
+        arches: &[],
+        required: &["include", "lib/libc.a"],
+        help: "point target.$TARGET.sdk at the sysroot Redox's cross toolchain installed \
+               relibc to",
+    },
+];
+
+/// Returns the SDK `target` is built against, if it's one in `SDK_TARGETS`.
+fn sdk_target(target: &str) -> Option<&'static SdkTarget> {
+    SDK_TARGETS.iter().find(|sdk| target.split('-').any(|part| part == sdk.os))
+}
+
+/// Returns the paths `sdk` has to contain for it to be usable for `target`.
+fn sdk_required_paths(sdk: &SdkTarget, target: &str) -> Vec<String> {
+    let arch = target.split('-').next().unwrap_or("");
+    let arch = sdk.arches.iter().find(|&&(triple, _)| triple == arch)
+        .map_or(arch, |&(_, name)| name);
+    sdk.required.iter().map(|path| path.replace("{arch}", arch)).collect()
+}
+
+/// The programs `remote-test-client spawn-emulator` runs to start the
+/// emulator for `target`, or `None` if it doesn't know how to.
+fn emulator_tools(target: &str) -> Option<&'static [&'static str]> {
+    if target.contains("android") {
+        return Some(&["adb"])
+    }
+    match target {
+        "arm-unknown-linux-gnueabihf" => Some(&["cpio", "qemu-system-arm"]),
+        "aarch64-unknown-linux-gnu" => Some(&["cpio", "qemu-system-aarch64"]),
+        _ => None,
+    }
+}
+
+fn check_test_emulators(s: &mut Sanity) -> Result<(), SanityError> {
+    match s.build.config.cmd {
+        Subcommand::Test { .. } | Subcommand::Bench { .. } => {}
+        _ => return Ok(()),
+    }
+    // With TEST_DEVICE_ADDR the tests connect to a device which is already
+    // running instead of starting an emulator.
+    if env::var_os("TEST_DEVICE_ADDR").is_some() {
+        return Ok(())
+    }
+    for target in &s.build.targets {
+        if !s.build.remote_tested(*target) {
+            continue
+        }
+        if let Some(rootfs) = s.build.qemu_rootfs(*target) {
+            if !rootfs.is_dir() {
+                return Err(SanityError::new(ErrorKind::Environment,
+                                            format!("target.{}.qemu-rootfs {} isn't a \
+                                                     directory", target, rootfs.display()))
+                           .about(rootfs))
+            }
+        }
+        let tools = match emulator_tools(target) {
+            Some(tools) => tools,
+            None => {
+                return Err(SanityError::new(ErrorKind::Config,
+                                            format!("the tests for {} would run in an emulator \
+                                                     since target.{}.qemu-rootfs is set, but \
+                                                     remote-test-client can't start one for \
+                                                     it; set TEST_DEVICE_ADDR to test on a \
+                                                     running device instead", target, target))
+                           .about(target))
+            }
+        };
+        for tool in tools {
+            let path = s.finder.must_have(tool).map_err(|mut e| {
+                e.message.push_str(&format!("The tests for {} need it to start their \
+                                             emulator.\n\n", target));
+                e
+            })?;
+            s.build.verbose(&format!("sanity: the emulator for {} runs {}",
+                                     target, path.display()));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the archive tools `x.py` runs, with what it runs each of them for.
+///
+/// rust-installer (`rust_installer` in dist.rs) writes and compresses every
+/// .tar.gz and .tar.xz which `dist` makes in-process, with the flate2 and xz2
+/// crates, so the formats themselves need nothing. It's unpacking which runs
+/// `tar`, and for `-z` GNU tar runs `gzip` too: `distcheck` unpacks the source
+/// tarballs it has just made, and `build.openssl-static` unpacks the OpenSSL
+/// sources for Cargo, which `dist` only builds for the extended distribution.
+fn archive_tools(build: &Build) -> Vec<(&'static str, &'static str)> {
+    let distcheck = match build.config.cmd {
+        Subcommand::Test { ref paths, .. } => paths.iter().any(|p| p.ends_with("distcheck")),
+        _ => false,
+    };
+    let openssl = match build.config.cmd {
+        Subcommand::Dist { .. } | Subcommand::Install { .. } => {
+            build.config.extended &&
+                build.hosts.iter().any(|host| build.openssl_install_dir(*host).is_some())
+        }
+        _ => false,
+    };
+    let mut tools = Vec::new();
+    if distcheck {
+        tools.push(("tar", "`distcheck` unpacks the source tarballs with `tar -xzf`."));
+        tools.push(("gzip", "`distcheck` unpacks the source tarballs with `tar -xzf`, which \
+                             runs it."));
+    }
+    if openssl {
+        tools.push(("tar", "build.openssl-static unpacks the OpenSSL sources with `tar zxf`."));
+        tools.push(("gzip", "build.openssl-static unpacks the OpenSSL sources with `tar zxf`, \
+                             which runs it."));
+    }
+    tools
+}
+
+fn check_archive_tools(s: &mut Sanity) -> Result<(), SanityError> {
+    for (tool, why) in archive_tools(s.build) {
+        s.finder.must_have(tool).map_err(|mut e| {
+            e.message.push_str(&format!("{}\n\n", why));
+            e
+        })?;
+    }
+    Ok(())
+}
+
+fn check_sdk_targets(s: &mut Sanity) -> Result<(), SanityError> {
+    for target in &s.build.targets {
+        let sdk = match sdk_target(target) {
+            Some(sdk) => sdk,
+            None => continue,
+        };
+        // Nothing else reads the SDK yet, so it's only checked when it's set.
+        let root = match s.build.config.target_config.get(target).and_then(|t| t.sdk.clone()) {
+            Some(root) => root,
+            None => continue,
+        };
+        if !root.is_dir() {
+            return Err(SanityError::new(ErrorKind::Environment,
+                                        format!("the {} SDK for {} doesn't exist at {}; {}",
+                                                sdk.name, target, root.display(), sdk.help))
+                .about(target))
+        }
+        for path in sdk_required_paths(sdk, target) {
+            if !root.join(&path).exists() {
+                return Err(SanityError::new(ErrorKind::Environment,
+                                            format!("the {} SDK at {} is missing {}, which {} \
+                                                     needs; {}",
+                                                    sdk.name, root.display(), path, target,
+                                                    sdk.help))
+                    .about(target))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Startup objects a musl-root needs for executables to link.
+const MUSL_CRT_OBJECTS: &[&str] = &["crt1.o", "crti.o", "crtn.o"];
+
+/// Looks for the startup object `obj` in the musl lib dir `lib`, either
+/// directly or in a per-architecture subdirectory like `x86_64-linux-musl`.
+fn find_crt_object(lib: &Path, target: &str, obj: &str) -> Option<PathBuf> {
+    let arch = target.split('-').next().unwrap_or("");
+    let env = target.split('-').last().unwrap_or("");
+    let dirs = [
+        lib.to_path_buf(),
+        lib.join(format!("{}-linux-{}", arch, env)),
+        lib.join(arch),
+    ];
+    dirs.iter().map(|dir| dir.join(obj)).find(|path| path.is_file())
+}
+
+fn check_cmake_msvc_generators(s: &mut Sanity) -> Result<(), SanityError> {
+    if !s.build.targets.iter().any(|target| target.contains("msvc")) {
+        return Ok(())
+    }
+    // There are three builds of cmake on windows: MSVC, MinGW, and
+    // Cygwin. The Cygwin build does not have generators for Visual
+    // Studio, so detect that here and error.
+    let out = probe_stdout(Command::new("cmake").arg("--help"))?;
+    if !out.contains("Visual Studio") {
+        return Err(SanityError::new(ErrorKind::MissingTool,
+                                    "
+cmake does not support Visual Studio generators.
+
+This is likely due to it being an msys/cygwin build of cmake,
+rather than the required windows version, built using MinGW
+or Visual Studio.
+
+If you are building under msys2 try installing the mingw-w64-x86_64-cmake
+package instead of cmake:
+
+$ pacman -R cmake && pacman -S mingw-w64-x86_64-cmake
+".to_string()).about("cmake"))
+    }
+    Ok(())
+}
+
+fn check_ccache(s: &mut Sanity) -> Result<(), SanityError> {
+    if let Some(ref ccache) = s.build.config.ccache {
+        s.finder.require(ccache)?;
+    }
+    Ok(())
+}
+
+fn check_ccache_sanitizers(s: &mut Sanity) -> Result<(), SanityError> {
+    // The sanitizer runtimes are built with flags which refer to absolute
+    // paths, so unless ccache is told to ignore those it can hand back
+    // instrumented objects from a different checkout or build directory.
+    if !s.build.config.sanitizers {
+        return Ok(())
+    }
+    if let Some(ref ccache) = s.build.config.ccache {
+        let path = s.finder.maybe_have(ccache)
+            .map_or(ccache.clone(), |path| path.display().to_string());
+        s.warnings.warn(format!("sanitizers are being built with ccache ({}), which can reuse \
+                                 stale instrumented objects; setting `base_dir = {}` and \
+                                 `hash_dir = false` in its ccache.conf avoids that",
+                                path, s.build.src.display()));
+    }
+    Ok(())
+}
+
+/// Returns whether `llvm.ccache` is set to sccache rather than ccache, going
+/// by its name.
+fn is_sccache(ccache: &str) -> bool {
+    Path::new(ccache).file_stem().map_or(false, |stem| stem == "sccache")
+}
+
+/// Extracts the version out of the first line of `sccache --version`, which
+/// is like `sccache 0.2.7`. Anything else isn't sccache.
+fn sccache_version(line: &str) -> Option<(u64, u64, u64)> {
+    if !line.trim().starts_with("sccache ") {
+        return None
+    }
+    parse_version(line)
+}
+
+fn check_sccache(s: &mut Sanity) -> Result<(), SanityError> {
+    let ccache = match s.build.config.ccache {
+        Some(ref ccache) if is_sccache(ccache) => ccache.clone(),
+        _ => return Ok(()),
+    };
+    // Not finding it at all is reported by `check_ccache`.
+    let sccache = match s.finder.maybe_have(&ccache) {
+        Some(sccache) => sccache,
+        None => return Ok(()),
+    };
+    let line = tool_version(&mut Command::new(&sccache));
+    if line.as_ref().and_then(|line| sccache_version(line)).is_none() {
+        s.warnings.warn(format!("llvm.ccache is {}, but `{} --version` doesn't say it's \
+                                 sccache ({:?})",
+                                ccache, sccache.display(), line.unwrap_or_default()));
+        return Ok(())
+    }
+    s.build.sccache_version = line.map(|line| line.trim().to_string());
+    if let Some(ref version) = s.build.sccache_version {
+        s.finder.versions.insert(sccache.clone(), version.clone());
+    }
+
+    // Starting the server fails when it's running already, in which case
+    // asking it for its statistics succeeds.
+    let start = probe(Command::new(&sccache).arg("--start-server"));
+    if start.as_ref().map_or(false, |out| out.status.success()) {
+        return Ok(())
+    }
+    let stats = probe(Command::new(&sccache).arg("--show-stats"));
+    if stats.map_or(false, |out| out.status.success()) {
+        return Ok(())
+    }
+    let why = match start {
+        Ok(out) => String::from_utf8_lossy(&out.stderr).trim().to_string(),
+        Err(e) => e.to_string(),
+    };
+    s.warnings.warn(format!("the sccache server can't be started or reached with {}, so \
+                             compiling LLVM through it will fail or go uncached: {}",
+                            sccache.display(), why));
+    Ok(())
+}
+
+/// Returns the path to the `stage0.txt` describing the bootstrap compiler.
+fn stage0_path(build: &Build) -> PathBuf {
+    build.config.stage0.clone().unwrap_or_else(|| build.src.join("src/stage0.txt"))
+}
+
+/// Returns the value of `key` in the contents of a `stage0.txt`, which has
+/// lines like `rustc: 1.27.0`.
+fn stage0_entry<'a>(contents: &'a str, key: &str) -> Option<&'a str> {
+    contents.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let colon = line.find(':')?;
+            if line[..colon].trim() == key { Some(line[colon + 1..].trim()) } else { None }
+        })
+        .next()
+}
+
+/// Returns the triples a compiler in `sysroot` runs on, which are those with
+/// codegen backends in its `lib/rustlib`, rather than just a standard
+/// library.
+fn sysroot_hosts(sysroot: &Path) -> Vec<String> {
+    let dir = match fs::read_dir(sysroot.join("lib/rustlib")) {
+        Ok(dir) => dir,
+        Err(_) => return Vec::new(),
+    };
+    let mut hosts = dir.filter_map(|e| e.ok())
+        .filter(|e| e.path().join("codegen-backends").is_dir())
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    hosts.sort();
+    hosts
+}
+
+fn check_stage0_host(s: &mut Sanity) -> Result<(), SanityError> {
+    // stage0.txt doesn't say which triple was downloaded, but the sysroot
+    // it was unpacked into does, without having to run it, which for the
+    // wrong architecture only fails with an exec format error.
+    let rustc = &s.build.initial_rustc;
+    let sysroot = match rustc.parent().and_then(|bin| bin.parent()) {
+        Some(sysroot) => sysroot,
+        None => return Ok(()),
+    };
+    let hosts = sysroot_hosts(sysroot);
+    if hosts.is_empty() || hosts.iter().any(|host| *host == *s.build.build) {
+        return Ok(())
+    }
+    Err(SanityError::new(ErrorKind::Config,
+                         format!("the stage0 compiler {} was built for {}, but the build \
+                                  machine is {}, so it can't be run to bootstrap with; is \
+                                  build.build or build.rustc wrong?",
+                                 rustc.display(), hosts.join(" and "), s.build.build))
+        .about(rustc))
+}
+
+fn check_stable_stage0(s: &mut Sanity) -> Result<(), SanityError> {
+    if s.build.config.channel == "stable" {
+        check_released_stage0(&stage0_path(s.build))?;
+    }
+    Ok(())
+}
+
+/// Returns the newest release which `stage0_rustc`, the `rustc` entry of
+/// `stage0.txt`, is older than, given the source tree is version `release`.
+///
+/// Each release is bootstrapped from the one before it, so anything older
+/// than that usually means a stage0 bump was forgotten. Channel names like
+/// `beta` can't be checked.
+fn outdated_stage0(stage0_rustc: &str, release: &str) -> Option<(u64, u64)> {
+    let (stage0_major, stage0_minor, _) = parse_version(stage0_rustc)?;
+    let (major, minor, _) = parse_version(release)?;
+    let expected = (major, minor.saturating_sub(1));
+    if (stage0_major, stage0_minor) < expected {
+        Some(expected)
+    } else {
+        None
+    }
+}
+
+fn check_stage0_version(s: &mut Sanity) -> Result<(), SanityError> {
+    let mut contents = String::new();
+    let path = stage0_path(s.build);
+    if File::open(&path).and_then(|mut f| f.read_to_string(&mut contents)).is_err() {
+        return Ok(())
+    }
+    let rustc = match stage0_entry(&contents, "rustc") {
+        Some(rustc) => rustc,
+        None => return Ok(()),
+    };
+    if let Some((major, minor)) = outdated_stage0(rustc, channel::CFG_RELEASE_NUM) {
+        s.warnings.warn(format!("{} bootstraps from rustc {}, but this is version {} which \
+                                 should bootstrap from {}.{}, was a stage0 bump forgotten?",
+                                path.display(), rustc, channel::CFG_RELEASE_NUM,
+                                major, minor));
+    }
+    Ok(())
+}
+
+/// Fails if the stage0 file at `path` bootstraps from a dev compiler.
+fn check_released_stage0(path: &Path) -> Result<(), SanityError> {
+    if !path.is_file() {
+        return Err(SanityError::new(ErrorKind::Config,
+                                    format!("the stage0 file {} doesn't exist, set \
+                                             build.stage0 in config.toml if it lives \
+                                             somewhere else", path.display())))
+    }
+    let mut stage0 = String::new();
+    if let Err(e) = File::open(path).and_then(|mut f| f.read_to_string(&mut stage0)) {
+        return Err(SanityError::new(ErrorKind::Environment,
+                                    format!("failed to read the stage0 file {}: {}",
+                                            path.display(), e)))
+    }
+    if stage0_entry(&stage0, "dev").is_some() {
+        return Err(SanityError::new(ErrorKind::Config,
+                                    "bootstrapping from a dev compiler in a stable release, \
+                                     but should only be bootstrapping from a released \
+                                     compiler!".to_string()))
+    }
+    Ok(())
+}
+
+/// The amount of free space below which we warn about the download cache, a
+/// little more than a stage0 rustc and cargo need.
+const MIN_CACHE_DIR_SPACE: u64 = 1 << 30;
+
+/// Creates `dir` if needed and ensures it is writable, failing with a
+/// message mentioning `what` it's used for otherwise.
+///
+/// Returns the free space on the filesystem containing `dir`, if that can be
+/// determined.
+fn ensure_writable_dir(what: &str, dir: &Path) -> Result<Option<u64>, SanityError> {
+    if let Err(e) = fs::create_dir_all(dir) {
+        return Err(SanityError::new(ErrorKind::Permission,
+                                    format!("failed to create the {} directory {}: {}",
+                                            what, dir.display(), e)))
+    }
+    if let Err(e) = probe_writable(dir) {
+        return Err(SanityError::new(ErrorKind::Permission,
+                                    format!("the {} directory {} is not writable: {}",
+                                            what, dir.display(), e)))
+    }
+    Ok(free_space(dir))
+}
+
+/// Checks `dir` is writable by creating and removing a file in it.
+fn probe_writable(dir: &Path) -> io::Result<()> {
+    // Include our pid so concurrent builds sharing a directory like `/tmp`
+    // don't trip over each other.
+    let probe = dir.join(format!(".rustbuild-sanity-{}", process::id()));
+    File::create(&probe)?;
+    fs::remove_file(&probe)
+}
+
+/// The amount of free space below which we refuse to use the temporary
+/// directory, as compilers and cmake put intermediate files there.
+const MIN_TEMP_DIR_SPACE: u64 = 64 << 20;
+
+/// The environment variables consulted for the temporary directory, in the
+/// order `env::temp_dir` consults them.
+#[cfg(windows)]
+const TEMP_DIR_VARS: &[&str] = &["TMP", "TEMP", "USERPROFILE"];
+#[cfg(not(windows))]
+const TEMP_DIR_VARS: &[&str] = &["TMPDIR"];
+
+/// Returns the temporary directory child processes will end up using, along
+/// with a description of where it came from, looking variables up with `var`.
+fn resolve_temp_dir(var: &Fn(&str) -> Option<OsString>) -> (PathBuf, String) {
+    for name in TEMP_DIR_VARS {
+        match var(name) {
+            Some(ref dir) if !dir.is_empty() => {
+                return (PathBuf::from(dir), format!("${}", name))
+            }
+            _ => {}
+        }
+    }
+    if cfg!(windows) {
+        (env::temp_dir(), "the Windows directory".to_string())
+    } else {
+        (PathBuf::from("/tmp"), "the default".to_string())
+    }
+}
+
+/// Returns the number of bytes available to us on the filesystem containing
+/// `path`.
+#[cfg(unix)]
+fn free_space(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::mem;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    unsafe {
+        let mut stat: libc::statvfs = mem::zeroed();
+        if libc::statvfs(path.as_ptr(), &mut stat) != 0 {
+            return None
+        }
+        Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+    }
+}
+
+#[cfg(windows)]
+fn free_space(path: &Path) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr;
+
+    extern "system" {
+        fn GetDiskFreeSpaceExW(lpDirectoryName: *const u16,
+                               lpFreeBytesAvailableToCaller: *mut u64,
+                               lpTotalNumberOfBytes: *mut u64,
+                               lpTotalNumberOfFreeBytes: *mut u64) -> i32;
+    }
+
+    let path = path.as_os_str().encode_wide().chain(Some(0)).collect::<Vec<u16>>();
+    let mut free = 0;
+    let ret = unsafe {
+        GetDiskFreeSpaceExW(path.as_ptr(), &mut free, ptr::null_mut(), ptr::null_mut())
+    };
+    if ret == 0 { None } else { Some(free) }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn free_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Returns the number of open files a build running `jobs` jobs at once
+/// comfortably fits in; linking LLVM or rustc alone opens hundreds.
+fn wanted_fd_limit(jobs: u32) -> u64 {
+    256 + 64 * jobs as u64
+}
+
+/// Returns the soft and hard limit on the number of files this process may
+/// have open.
+#[cfg(unix)]
+fn fd_limits() -> Option<(u64, u64)> {
+    use std::mem;
+
+    unsafe {
+        let mut limit: libc::rlimit = mem::zeroed();
+        if libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) != 0 {
+            return None
+        }
+        Some((limit.rlim_cur as u64, limit.rlim_max as u64))
+    }
+}
+
+#[cfg(not(unix))]
+fn fd_limits() -> Option<(u64, u64)> {
+    None
+}
+
+/// Sets the soft limit on open files to `soft`, returning whether that
+/// worked.
+#[cfg(unix)]
+fn set_fd_limit(soft: u64, hard: u64) -> bool {
+    let limit = libc::rlimit {
+        rlim_cur: soft as libc::rlim_t,
+        rlim_max: hard as libc::rlim_t,
+    };
+    unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) == 0 }
+}
+
+#[cfg(not(unix))]
+fn set_fd_limit(_soft: u64, _hard: u64) -> bool {
+    false
+}
+
+fn check_fd_limit(s: &mut Sanity) -> Result<(), SanityError> {
+    let (soft, hard) = match fd_limits() {
+        Some(limits) => limits,
+        None => return Ok(()),
+    };
+    let jobs = s.build.jobs();
+    let wanted = wanted_fd_limit(jobs);
+    if soft >= wanted {
+        return Ok(())
+    }
+    // Whatever we run inherits the limit, so raising ours is enough.
+    let mut current = soft;
+    let raised = cmp::min(wanted, hard);
+    if s.build.config.raise_fd_limit && raised > soft && set_fd_limit(raised, hard) {
+        s.build.verbose(&format!("raised the limit on open files from {} to {}",
+                                 soft, raised));
+        current = raised;
+    }
+    if current >= wanted {
+        return Ok(())
+    }
+    let hint = if s.build.config.raise_fd_limit {
+        "raise the hard limit"
+    } else {
+        "raise it with `ulimit -n` or build.raise-fd-limit"
+    };
+    s.warnings.warn(format!("at most {} files can be open at once (the hard limit is {}), \
+                             but building with {} jobs wants about {}, and running out shows \
+                             up as \"too many open files\" failures midway through; {}, or \
+                             run fewer jobs",
+                            current, hard, jobs, wanted, hint));
+    Ok(())
+}
+
+/// How many jobs per logical CPU are still reasonable; past this the jobs
+/// mostly wait on each other and on memory.
+const MAX_JOBS_PER_CPU: u32 = 4;
+
+/// Roughly what linking one of LLVM's tools takes, which is most of what
+/// building LLVM needs at once if every job links together.
+const LLVM_LINK_MEMORY: u64 = 1 << 30;
+
+/// Returns what's wrong with running `jobs` jobs, `link_jobs` of which link
+/// LLVM at once if LLVM is built, on `cpus` logical CPUs and `memory` bytes
+/// of memory, if it's known.
+fn parallelism_problem(jobs: u32, link_jobs: Option<u32>, cpus: u32, memory: Option<u64>)
+                       -> Option<String> {
+    let memory_desc = match memory {
+        Some(memory) => format!(" and {} of memory", human_size(memory)),
+        None => String::new(),
+    };
+    if jobs > MAX_JOBS_PER_CPU * cpus {
+        return Some(format!("building with {} jobs on {} logical CPUs{} mostly makes the jobs \
+                             wait on each other; run fewer with `-j` or build.jobs",
+                            jobs, cpus, memory_desc))
+    }
+    match (link_jobs, memory) {
+        (Some(links), Some(memory)) if links as u64 * LLVM_LINK_MEMORY > memory => {
+            Some(format!("building LLVM with {} jobs on {} logical CPUs{} can link up to {} \
+                          tools at once, each taking about {}, which can run out of memory; \
+                          set llvm.link-jobs to link fewer at once",
+                         jobs, cpus, memory_desc, links, human_size(LLVM_LINK_MEMORY)))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the `MemTotal` of `/proc/meminfo`, in bytes.
+fn meminfo_total(meminfo: &str) -> Option<u64> {
+    meminfo.lines()
+        .filter(|line| line.starts_with("MemTotal:"))
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .filter_map(|kib| kib.parse::<u64>().ok())
+        .next()
+        .map(|kib| kib * 1024)
+}
+
+#[cfg(target_os = "linux")]
+fn total_memory() -> Option<u64> {
+    let mut meminfo = String::new();
+    match File::open("/proc/meminfo").and_then(|mut f| f.read_to_string(&mut meminfo)) {
+        Ok(_) => meminfo_total(&meminfo),
+        Err(_) => None,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn total_memory() -> Option<u64> {
+    None
+}
+
+fn check_jobs(s: &mut Sanity) -> Result<(), SanityError> {
+    let jobs = s.build.jobs();
+    // Without llvm.link-jobs ninja links with as many jobs as it compiles.
+    let link_jobs = if building_llvm(s.build) {
+        Some(s.build.config.llvm_link_jobs.filter(|&n| n > 0).unwrap_or(jobs))
+    } else {
+        None
+    };
+    let cpus = num_cpus::get() as u32;
+    if let Some(problem) = parallelism_problem(jobs, link_jobs, cpus, total_memory()) {
+        s.warnings.warn(problem);
+    }
+    Ok(())
+}
+
+fn human_size(bytes: u64) -> String {
+    if bytes >= 1 << 30 {
+        format!("{:.1} GiB", bytes as f64 / (1u64 << 30) as f64)
+    } else {
+        format!("{:.1} MiB", bytes as f64 / (1u64 << 20) as f64)
+    }
+}
+
+/// The sanitizers we build runtimes for on `target`, mirroring `compile.rs`
+/// and the `rustc_*san` crates.
+fn supported_sanitizers(target: &str) -> &'static [&'static str] {
+    match target {
+        "x86_64-unknown-linux-gnu" => &["asan", "lsan", "msan", "tsan"],
+        "x86_64-apple-darwin" => &["asan", "tsan"],
+        _ => &[],
+    }
+}
+
+/// Fails if `build.profiler` is enabled but the profiler runtime's sources
+/// aren't there to build it from.
+fn check_profiler(s: &mut Sanity) -> Result<(), SanityError> {
+    if !s.build.config.profiler {
+        return Ok(())
+    }
+    // `profiler_builtins` compiles these straight out of compiler-rt, so all
+    // it needs beyond them is the C compiler we've already checked.
+    let sources = s.build.src.join("src/libcompiler_builtins/compiler-rt/lib/profile");
+    if !sources.join("InstrProfiling.c").is_file() {
+        return Err(SanityError::new(ErrorKind::Environment,
+                                    format!("build.profiler is enabled but the profiler \
+                                             runtime sources weren't found in {}; is the \
+                                             src/libcompiler_builtins submodule checked out?",
+                                            sources.display())))
+    }
+    Ok(())
+}
+
+fn check_llvm_profdata(s: &mut Sanity) -> Result<(), SanityError> {
+    if !s.build.config.profiler {
+        return Ok(())
+    }
+    for host in &s.build.hosts {
+        // When we build LLVM ourselves llvm-profdata comes along with it,
+        // but an external LLVM may well have been packaged without it.
+        let llvm_config = match s.build.config.target_config.get(host)
+                                   .and_then(|config| config.llvm_config.as_ref()) {
+            Some(llvm_config) => llvm_config,
+            None => continue,
+        };
+        let name = exe("llvm-profdata", host);
+        let beside = llvm_config.parent().map(|dir| dir.join(&name));
+        if beside.as_ref().map_or(false, |path| path.is_file()) {
+            continue
+        }
+        if s.finder.maybe_have(&name).is_some() {
+            continue
+        }
+        s.warnings.warn(format!("build.profiler is enabled but llvm-profdata wasn't found next \
+                                 to {} or in PATH; it's needed to merge the profiles that \
+                                 instrumented programs for {} write",
+                                llvm_config.display(), host));
+    }
+    Ok(())
+}
+
+/// Returns the major and minor version in the `--version` output of an LLVM
+/// tool, which looks like `LLVM (http://llvm.org/):\n  LLVM version 6.0.0`,
+/// or llvm-config's plain `6.0.0svn`.
+fn llvm_release(version: &str) -> Option<(u64, u64)> {
+    parse_version(version).map(|(major, minor, _)| (major, minor))
+}
+
+fn check_coverage_tools(s: &mut Sanity) -> Result<(), SanityError> {
+    if !s.build.config.profiler {
+        return Ok(())
+    }
+    for host in &s.build.hosts {
+        // Profiles written by programs instrumented by one LLVM often can't
+        // be merged by another's llvm-profdata, so the tools have to come
+        // from the same release as the compiler. Until we've built LLVM
+        // ourselves there's nothing to compare against.
+        let llvm_config = match s.build.config.target_config.get(host)
+                                   .and_then(|config| config.llvm_config.clone()) {
+            Some(llvm_config) => llvm_config,
+            None => s.build.llvm_out(*host).join("bin").join(exe("llvm-config", host)),
+        };
+        if !llvm_config.is_file() {
+            continue
+        }
+        let llvm_version = match probe(Command::new(&llvm_config).arg("--version")) {
+            Ok(ref out) if out.status.success() => {
+                String::from_utf8_lossy(&out.stdout).trim().to_string()
+            }
+            // An llvm-config which doesn't run is reported when the build
+            // checks its version.
+            _ => continue,
+        };
+        let release = match llvm_release(&llvm_version) {
+            Some(release) => release,
+            None => continue,
+        };
+        for tool in &["llvm-profdata", "llvm-cov"] {
+            let name = exe(tool, host);
+            // Only the tools next to llvm-config come with the compiler's
+            // LLVM. The build never runs either, so one in PATH is whatever
+            // the user wants to merge profiles with, not ours to judge.
+            let path = match llvm_config.parent().map(|dir| dir.join(&name)) {
+                Some(ref path) if path.is_file() => path.clone(),
+                _ => continue,
+            };
+            let out = match probe(Command::new(&path).arg("--version")) {
+                Ok(ref out) if out.status.success() => {
+                    String::from_utf8_lossy(&out.stdout).into_owned()
+                }
+                _ => continue,
+            };
+            if llvm_release(&out) == Some(release) {
+                continue
+            }
+            let found = out.lines()
+                .map(|line| line.trim())
+                .find(|line| line.contains("version"))
+                .unwrap_or("an unknown version")
+                .to_string();
+            return Err(SanityError::new(ErrorKind::BadVersion,
+                                        format!("build.profiler is enabled but {} isn't from \
+                                                 the same LLVM release as the compiler for {}, \
+                                                 so it may fail to merge their profiles\n\
+                                                 \t{}: {}\n\
+                                                 \t{}: LLVM version {}",
+                                                tool, host, path.display(), found,
+                                                llvm_config.display(), llvm_version))
+                       .about(&path))
+        }
+    }
+    Ok(())
+}
+
+/// Returns the name LLVM gives the backend which generates code for
+/// `target`, as printed by `llvm-config --targets-built`, or `None` if we
+/// don't know it.
+fn llvm_backend(target: &str) -> Option<&'static str> {
+    // Emscripten targets use emscripten's LLVM fork rather than the
+    // configured LLVM.
+    if target.contains("emscripten") {
+        return None
+    }
+    let arch = target.split('-').next().unwrap_or("");
+    let backend = match arch {
+        "x86_64" | "i386" | "i586" | "i686" => "X86",
+        "aarch64" => "AArch64",
+        "mips" | "mipsel" | "mips64" | "mips64el" => "Mips",
+        "powerpc" | "powerpc64" | "powerpc64le" => "PowerPC",
+        "sparc" | "sparc64" | "sparcv9" => "Sparc",
+        "s390x" => "SystemZ",
+        "msp430" => "MSP430",
+        "hexagon" => "Hexagon",
+        "wasm32" => "WebAssembly",
+        _ if arch.starts_with("arm") || arch.starts_with("thumb") => "ARM",
+        _ if arch.starts_with("riscv") => "RISCV",
+        _ if arch.starts_with("nvptx") => "NVPTX",
+        _ => return None,
+    };
+    Some(backend)
+}
+
+/// Returns the backends `targets` need which aren't in `built`, along with
+/// the targets needing them.
+fn missing_llvm_backends<'a>(targets: &[&'a str], built: &[&str])
+                             -> Vec<(&'static str, Vec<&'a str>)> {
+    let mut missing: Vec<(&'static str, Vec<&'a str>)> = Vec::new();
+    for &target in targets {
+        let backend = match llvm_backend(target) {
+            Some(backend) => backend,
+            None => continue,
+        };
+        if built.contains(&backend) {
+            continue
+        }
+        match missing.iter().position(|&(name, _)| name == backend) {
+            Some(i) => missing[i].1.push(target),
+            None => missing.push((backend, vec![target])),
+        }
+    }
+    missing
+}
+
+fn check_llvm_backends(s: &mut Sanity) -> Result<(), SanityError> {
+    let targets = s.build.targets.iter().map(|t| &t[..]).collect::<Vec<_>>();
+    for host in &s.build.hosts {
+        // Every host's compiler generates code for every target with that
+        // host's LLVM, so an external one needs all of their backends.
+        let llvm_config = match s.build.config.target_config.get(host)
+                                   .and_then(|config| config.llvm_config.as_ref()) {
+            Some(llvm_config) => llvm_config,
+            None => continue,
+        };
+        let out = match probe(Command::new(llvm_config).arg("--targets-built")) {
+            Ok(ref out) if out.status.success() => {
+                String::from_utf8_lossy(&out.stdout).into_owned()
+            }
+            // An llvm-config which doesn't run is reported when the build
+            // checks its version.
+            _ => continue,
+        };
+        let built = out.split_whitespace().collect::<Vec<_>>();
+        let missing = missing_llvm_backends(&targets, &built);
+        if missing.is_empty() {
+            continue
+        }
+        let missing = missing.iter()
+            .map(|&(backend, ref targets)| format!("{} (for {})", backend, targets.join(", ")))
+            .collect::<Vec<_>>();
+        return Err(SanityError::new(ErrorKind::Environment,
+                                    format!("the LLVM of {} lacks backends needed by the \
+                                             configured targets\n\
+                                             \tllvm-config: {}\n\
+                                             \tbuilt: {}\n\
+                                             \tmissing: {}",
+                                            host, llvm_config.display(), built.join(" "),
+                                            missing.join(", ")))
+                   .about(llvm_config))
+    }
+    Ok(())
+}
+
+/// Warns about any sanitizer runtime library that the C compiler for `target`
+/// doesn't appear to have installed.
+fn check_sanitizer_runtimes(build: &Build, target: Interned<String>, warnings: &mut Warnings) {
+    let sanitizers = supported_sanitizers(&target);
+    if sanitizers.is_empty() {
+        return
+    }
+    let cc = build.cc(target);
+
+    // Clang keeps its runtimes in its resource directory, e.g.
+    // `lib/linux/libclang_rt.asan-x86_64.a` or
+    // `lib/darwin/libclang_rt.asan_osx_dynamic.dylib`.
+    let resource_dir = probe(Command::new(cc).arg("-print-resource-dir")).ok()
+        .and_then(|out| {
+            if out.status.success() {
+                Some(PathBuf::from(String::from_utf8_lossy(&out.stdout).trim()))
+            } else {
+                None
+            }
+        });
+    if let Some(resource_dir) = resource_dir {
+        let os = if target.contains("apple") { "darwin" } else { "linux" };
+        let lib_dir = resource_dir.join("lib").join(os);
+        let files = fs::read_dir(&lib_dir).map(|dir| {
+            dir.filter_map(|e| e.ok())
+               .map(|e| e.file_name().to_string_lossy().into_owned())
+               .collect::<Vec<_>>()
+        }).unwrap_or_default();
+        for sanitizer in sanitizers {
+            let prefix = format!("libclang_rt.{}", sanitizer);
+            if !files.iter().any(|f| f.starts_with(&prefix)) {
+                warnings.warn(format!("sanitizers are enabled but no {} runtime for {} was \
+                                       found in {} ({})",
+                                      sanitizer, target, lib_dir.display(), cc.display()));
+            }
+        }
+        return
+    }
+
+    // GCC doesn't have a resource directory, but will tell us where the
+    // runtime libraries it would link against are. If it can't find one it
+    // just prints the name back. It doesn't support msan at all.
+    for sanitizer in sanitizers.iter().filter(|s| **s != "msan") {
+        let name = format!("lib{}.so", sanitizer);
+        let found = probe(Command::new(cc).arg(format!("-print-file-name={}", name))).ok()
+            .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string());
+        match found {
+            Some(ref path) if path != &name => {}
+            _ => {
+                warnings.warn(format!("sanitizers are enabled but {} couldn't find the {} \
+                                       runtime ({}) for {}",
+                                      cc.display(), sanitizer, name, target));
+            }
+        }
+    }
+}
+
+/// Returns the object file format that code for `target` is compiled to.
+fn object_format(target: &str) -> &'static str {
+    if target.contains("apple") {
+        "Mach-O"
+    } else if target.contains("windows") || target.contains("uefi") {
+        "COFF"
+    } else if target.starts_with("wasm32") {
+        "wasm"
+    } else {
+        "ELF"
+    }
+}
+
+/// Returns the only object format `linker` supports, if it's a linker whose
+/// name tells us that.
+///
+/// Compiler drivers like `cc` or `clang` could be driving any linker, so
+/// those are only recognized when they're a cross compiler whose prefix
+/// gives the platform away.
+fn known_linker_format(linker: &Path) -> Option<&'static str> {
+    let name = linker.file_name()?.to_string_lossy().to_lowercase();
+    let name = name.trim_right_matches(".exe");
+    if name == "link" || name == "lld-link" || name.contains("mingw32") {
+        Some("COFF")
+    } else if name == "ld64" || name == "ld64.lld" || name.contains("apple-darwin") {
+        Some("Mach-O")
+    } else if name == "wasm-ld" {
+        Some("wasm")
+    } else if name == "ld.lld" || name.contains("-linux-") || name.contains("-elf-") ||
+              name.contains("-eabi-") || name.contains("-eabihf-") {
+        Some("ELF")
+    } else {
+        None
+    }
+}
+
+/// Asks `linker`, which may be a compiler driver, which object formats it
+/// supports by looking through the `supported targets` GNU ld and gold
+/// print along with their `--help`.
+fn probe_linker_formats(linker: &Path) -> Option<Vec<&'static str>> {
+    let mut cmd = Command::new(linker);
+    let is_ld = linker.file_name()
+        .map_or(false, |name| name.to_string_lossy().ends_with("ld"));
+    cmd.arg(if is_ld { "--help" } else { "-Wl,--help" });
+    let output = probe(&mut cmd).ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = stdout.lines().find(|line| line.contains("supported targets:"))?;
+    Some(bfd_target_formats(line))
+}
+
+/// Maps the BFD target names ld prints, like `elf64-x86-64` or `pei-i386`,
+/// to the object formats we care about.
+fn bfd_target_formats(line: &str) -> Vec<&'static str> {
+    let mut formats = Vec::new();
+    for name in line.split_whitespace() {
+        let format = if name.starts_with("elf") {
+            "ELF"
+        } else if name.starts_with("pe-") || name.starts_with("pei-") {
+            "COFF"
+        } else if name.starts_with("mach-o") {
+            "Mach-O"
+        } else {
+            continue
+        };
+        if !formats.contains(&format) {
+            formats.push(format);
+        }
+    }
+    formats
+}
+
+/// Static libraries the C++ compiler of a statically linked host needs.
+const STATIC_HOST_LIBS: &[&str] = &["libstdc++.a", "libgcc.a", "libgcc_eh.a"];
+
+/// Fails if the C++ compiler for `host` can't find the static libraries
+/// listed in `STATIC_HOST_LIBS`.
+fn check_static_host_libs(build: &Build, host: Interned<String>) -> Result<(), SanityError> {
+    let cxx = build.cxx(host).unwrap();
+    let out = probe_stdout(Command::new(cxx).arg("-print-search-dirs"))?;
+    let dirs = library_search_dirs(&out);
+    let missing = missing_libs(&dirs, STATIC_HOST_LIBS);
+    if !missing.is_empty() {
+        let searched = dirs.iter()
+            .map(|d| format!("    {}", d.display()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        return Err(SanityError::new(ErrorKind::Environment,
+                                    format!("\n\n{} is built statically but {} couldn't find \
+                                             {} in any of:\n{}\n\n",
+                                            host, cxx.display(), missing.join(", "), searched))
+            .about(host))
+    }
+    Ok(())
+}
+
+/// Parses the `libraries:` line of `cc -print-search-dirs`.
+fn library_search_dirs(output: &str) -> Vec<PathBuf> {
+    output.lines()
+        .filter(|line| line.starts_with("libraries:"))
+        .flat_map(|line| {
+            let dirs = line["libraries:".len()..].trim().trim_left_matches('=');
+            dirs.split(':').filter(|d| !d.is_empty()).map(PathBuf::from).collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Returns which of `libs` doesn't exist in any of `dirs`.
+fn missing_libs<'a>(dirs: &[PathBuf], libs: &[&'a str]) -> Vec<&'a str> {
+    libs.iter()
+        .filter(|lib| !dirs.iter().any(|dir| dir.join(lib).is_file()))
+        .cloned()
+        .collect()
+}
+
+/// Extracts the first `major.minor[.patch]` version number out of the output
+/// of a `--version` invocation.
+///
+/// All of the tools we probe like to decorate their versions differently, for
+/// example `git version 2.17.1.windows.2`, `cmake version 3.11.0-rc2`,
+/// `v8.11.1` or `lldb-1000.11.37.1`, so this deliberately just looks for the
+/// first run of at least two dot-separated numbers and ignores anything after
+/// the third. A missing patch version is reported as 0.
+fn parse_version(s: &str) -> Option<(u64, u64, u64)> {
+    let bytes = s.as_bytes();
+    for start in 0..bytes.len() {
+        // Only start at the beginning of a number, so `x86_64` or the tail of
+        // an earlier, shorter number aren't picked up.
+        if !bytes[start].is_ascii_digit() {
+            continue
+        }
+        if start > 0 && (bytes[start - 1].is_ascii_digit() || bytes[start - 1] == b'.') {
+            continue
+        }
+
+        let mut parts = Vec::new();
+        let mut rest = &s[start..];
+        loop {
+            let len = rest.bytes().take_while(|b| b.is_ascii_digit()).count();
+            if len == 0 {
+                break
+            }
+            match rest[..len].parse::<u64>() {
+                Ok(n) => parts.push(n),
+                Err(_) => break,
+            }
+            rest = &rest[len..];
+            if parts.len() == 3 || !rest.starts_with('.') {
+                break
+            }
+            rest = &rest[1..];
+        }
+        if parts.len() >= 2 {
+            return Some((parts[0], parts[1], parts.get(2).cloned().unwrap_or(0)))
+        }
+    }
+    None
+}
+
+thread_local! {
+    /// How long each command `probe` ran took, for `build.print-sanity-timings`.
+    static PROBE_TIMINGS: RefCell<Vec<(String, Duration)>> = RefCell::new(Vec::new());
+}
+
+/// How long a tool gets to print its version or help before it's assumed to
+/// hang, like an lldb waiting on a code signing prompt on macOS does.
+const PROBE_TIMEOUT_SECS: u64 = 10;
+
+/// Runs `cmd` to completion, recording how long it took.
+fn probe(cmd: &mut Command) -> io::Result<Output> {
+    probe_within(cmd, None)
+}
+
+/// Like `probe`, but for a tool which is only asked something like its
+/// version, so it's killed if it takes longer than `PROBE_TIMEOUT_SECS`.
+fn quick_probe(cmd: &mut Command) -> io::Result<Output> {
+    probe_within(cmd, Some(Duration::from_secs(PROBE_TIMEOUT_SECS)))
+}
+
+fn probe_within(cmd: &mut Command, timeout: Option<Duration>) -> io::Result<Output> {
+    #[cfg(test)]
+    {
+        if let Some(output) = __test::canned_output(cmd) {
+            return Ok(output)
+        }
+    }
+    let start = Instant::now();
+    let output = match timeout {
+        Some(timeout) => output_within(cmd, timeout),
+        None => cmd.output(),
+    };
+    record_probe(cmd, start);
+    output
+}
+
+/// Like `Command::output`, but kills and reaps `cmd` if it hasn't finished
+/// after `timeout`, failing with `io::ErrorKind::TimedOut`.
+fn output_within(cmd: &mut Command, timeout: Duration) -> io::Result<Output> {
+    let mut child = cmd.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // The pipes are read as the child runs, as it'd block once it has
+    // written more than fits in them.
+    let stdout = read_on_thread(child.stdout.take());
+    let stderr = read_on_thread(child.stderr.take());
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status
+        }
+        if start.elapsed() >= timeout {
+            let _ = child.kill();
+            child.wait()?;
+            return Err(io::Error::new(io::ErrorKind::TimedOut,
+                                      format!("{:?} didn't finish within {} seconds",
+                                              cmd, timeout.as_secs())))
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+fn read_on_thread<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut contents = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut contents);
+        }
+        contents
+    })
+}
+
+/// Runs `cmd` for its stdout, failing unless it runs successfully within
+/// `PROBE_TIMEOUT_SECS`.
+fn probe_stdout(cmd: &mut Command) -> Result<String, SanityError> {
+    let out = match quick_probe(cmd) {
+        Ok(out) => out,
+        Err(e) => {
+            return Err(SanityError::new(ErrorKind::MissingTool,
+                                        format!("\n\ncouldn't run {:?}: {}\n\n", cmd, e)))
+        }
+    };
+    if !out.status.success() {
+        return Err(SanityError::new(ErrorKind::MissingTool,
+                                    format!("\n\n{:?} failed with {}:\n{}\n\n",
+                                            cmd, out.status,
+                                            String::from_utf8_lossy(&out.stderr).trim())))
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+fn record_probe(cmd: &Command, start: Instant) {
+    let elapsed = start.elapsed();
+    PROBE_TIMINGS.with(|timings| timings.borrow_mut().push((format!("{:?}", cmd), elapsed)));
+}
+
+/// Prints `timings`, slowest first, in the same format as
+/// `build.print-step-timings`.
+fn print_timings(kind: &str, timings: &mut Vec<(String, Duration)>) {
+    timings.sort_by(|a, b| b.1.cmp(&a.1));
+    for &(ref what, dur) in timings.iter() {
+        println!("[TIMING] sanity {} {} -- {}.{:03}",
+                 kind, what, dur.as_secs(), dur.subsec_nanos() / 1_000_000);
+    }
+}
+
+/// Extracts the version out of the output of `cmake --version`, which starts
+/// with a line like `cmake version 3.11.4` (or `cmake3 version` on the distros
+/// which rename it).
+fn cmake_version(output: &str) -> Option<(u64, u64, u64)> {
+    let line = output.lines().next()?;
+    if !line.contains(" version ") {
+        return None
+    }
+    parse_version(line)
+}
+
+/// Runs `cmd --version`, returning the first line of its output if it ran
+/// successfully.
+fn tool_version(cmd: &mut Command) -> Option<String> {
+    let output = quick_probe(cmd.arg("--version")).ok()?;
+    if !output.status.success() {
+        return None
+    }
+    String::from_utf8_lossy(&output.stdout).lines().next().map(|s| s.to_string())
+}
+
+/// Parses a version given in `config.toml`, which is one to three
+/// dot-separated numbers like `7` or `3.11.4`.
+fn parse_version_constraint(version: &str) -> Result<Vec<u64>, String> {
+    let parts = version.split('.').map(|part| {
+        part.parse::<u64>().map_err(|_| format!("{:?} isn't a number", part))
+    }).collect::<Result<Vec<_>, _>>()?;
+    if parts.len() > 3 {
+        return Err("expected at most three components, like `7.3.0`".to_string())
+    }
+    Ok(parts)
+}
+
+/// Fails if any of the versions in `config` don't parse, so typos are
+/// reported before anything is probed.
+fn validate_versions(config: &Config) -> Result<(), SanityError> {
+    let mut targets = config.target_config.keys().collect::<Vec<_>>();
+    targets.sort();
+    for target in targets {
+        if let Some(ref max) = config.target_config[target].cc_max_version {
+            if let Err(e) = parse_version_constraint(max) {
+                return Err(SanityError::new(ErrorKind::Config,
+                                            format!("invalid value for \
+                                                     target.{}.cc-max-version in \
+                                                     config.toml: {:?}, {}", target, max, e))
+                    .about(target))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Two options which contradict each other when set together.
+struct Conflict {
+    first: &'static str,
+    second: &'static str,
+    /// Whether both are set in `config`.
+    set: fn(&Config) -> bool,
+    /// What happens when both are set, and which one to drop.
+    why: &'static str,
+}
+
+/// Every known pair of contradictory options. Only options whose defaults
+/// can't conflict belong here, so that hitting one always means both were
+/// asked for.
+static CONFLICTS: &[Conflict] = &[
+    Conflict {
+        first: "llvm.enabled = false",
+        second: "rust.lld = true",
+        set: conflict_llvm_disabled_lld,
+        why: "lld is built from the LLVM tree, so LLVM would be built anyway; drop \
+              rust.lld or enable LLVM",
+    },
+    Conflict {
+        first: "llvm.enabled = false",
+        second: "build.sanitizers = true",
+        set: conflict_llvm_disabled_sanitizers,
+        why: "the sanitizer runtimes are built against llvm-config, so LLVM would be built \
+              anyway; drop build.sanitizers or enable LLVM",
+    },
+    Conflict {
+        first: "--sanity-diff",
+        second: "--sanity-only",
+        set: conflict_sanity_diff_only,
+        why: "--sanity-diff exits before any check runs, so --sanity-only would never \
+              report anything; drop one of them",
+    },
+];
+
+fn conflict_llvm_disabled_lld(config: &Config) -> bool {
+    !config.llvm_enabled && config.lld_enabled
+}
+
+fn conflict_llvm_disabled_sanitizers(config: &Config) -> bool {
+    !config.llvm_enabled && config.sanitizers
+}
+
+fn conflict_sanity_diff_only(config: &Config) -> bool {
+    config.sanity_diff && config.sanity_only
+}
+
+/// Fails if `config` sets any pair of `CONFLICTS`, since one of the two
+/// would be silently ignored or break the build.
+fn validate_conflicts(config: &Config) -> Result<(), SanityError> {
+    match CONFLICTS.iter().find(|conflict| (conflict.set)(config)) {
+        Some(conflict) => {
+            Err(SanityError::new(ErrorKind::Config,
+                                 format!("{} conflicts with {}: {}",
+                                         conflict.first, conflict.second, conflict.why)))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Warns if the C compiler for `target` is newer than `max`, the value of
+/// `target.$target.cc-max-version`.
+fn check_cc_max_version(build: &Build,
+                        target: Interned<String>,
+                        max: &str,
+                        warnings: &mut Warnings)
+                        -> Result<(), SanityError> {
+    // `cl.exe` doesn't understand `--version`, and nobody has asked for this
+    // on MSVC yet.
+    if target.contains("msvc") {
+        return Ok(())
+    }
+    let allowed = match parse_version_constraint(max) {
+        Ok(allowed) => allowed,
+        Err(e) => {
+            return Err(SanityError::new(ErrorKind::Config,
+                                        format!("invalid target.{}.cc-max-version {:?}: {}",
+                                                target, max, e)).about(target))
+        }
+    };
+    let cc = build.cc(target);
+    let line = match tool_version(&mut Command::new(cc)) {
+        Some(line) => line,
+        None => return Ok(()),
+    };
+    let (major, minor, patch) = match parse_version(&line) {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+    // Only compare as many components as were given, so `7` allows any 7.x.
+    let found = [major, minor, patch];
+    if found[..allowed.len()] > allowed[..] {
+        warnings.warn(format!("the C compiler for {} ({}) is version {}.{}.{}, which is newer \
+                               than the configured cc-max-version of {}",
+                              target, cc.display(), major, minor, patch, max));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod __test {
+    use super::*;
+    use cache::INTERNER;
+    use config::Target;
+    use DocTests;
+
+    /// Creates an empty scratch directory unique to the test `name`.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join("rustbuild-sanity-tests").join(name);
+        let _ = fs::remove_dir_all(&dir);
+        t!(fs::create_dir_all(&dir));
+        dir
+    }
+
+    fn touch(path: &Path) {
+        t!(File::create(path));
+    }
+
+    #[test]
+    fn missing_musl_archives() {
+        let dir = scratch_dir("missing_musl_archives");
+        let (n32, n64) = (dir.join("n32/lib"), dir.join("n64/lib"));
+        t!(fs::create_dir_all(&n32));
+        t!(fs::create_dir_all(&n64));
+        touch(&n32.join("libc.a"));
+        touch(&n32.join("libunwind.a"));
+        touch(&n64.join("libc.a"));
+        assert_eq!(missing_musl_archive(&n32), None);
+        assert_eq!(missing_musl_archive(&n64), Some("libunwind.a"));
+        assert_eq!(missing_musl_archive(&dir.join("o32/lib")), Some("libc.a"));
+    }
+
+    #[test]
+    fn check_names_are_unique() {
+        for (i, a) in CHECKS.iter().enumerate() {
+            assert!(!a.name.is_empty() && !a.name.contains(' '), "bad name {:?}", a.name);
+            for b in &CHECKS[i + 1..] {
+                assert!(a.name != b.name, "duplicate check {:?}", a.name);
+            }
+        }
+    }
+
+    #[test]
+    fn exit_codes_are_distinct() {
+        let kinds = [ErrorKind::MissingTool, ErrorKind::BadVersion, ErrorKind::Config,
+                     ErrorKind::Permission, ErrorKind::Environment, ErrorKind::DeniedWarnings];
+        for (i, a) in kinds.iter().enumerate() {
+            assert!(a.exit_code() > 1, "{:?} uses a reserved exit code", a);
+            for b in &kinds[i + 1..] {
+                assert!(a.exit_code() != b.exit_code(), "{:?} and {:?} share a code", a, b);
+            }
+        }
+    }
+
+    /// Hands out paths from a fixed table, like an embedder would.
+    struct CannedResolver(HashMap<&'static str, &'static str>);
+
+    impl ToolResolver for CannedResolver {
+        fn resolve(&mut self, name: &OsStr) -> Option<PathBuf> {
+            name.to_str().and_then(|name| self.0.get(name)).map(PathBuf::from)
+        }
+    }
+
+    /// What a check made of a configuration.
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    enum Outcome {
+        Passed,
+        Warned,
+        Failed(ErrorKind),
+    }
+
+    const HOST: &str = "x86_64-unknown-linux-gnu";
+
+    fn target(config: &mut Config, target: &str) {
+        config.targets.push(INTERNER.intern_str(target));
+    }
+
+    fn host(config: &mut Config, host: &str) {
+        config.hosts.push(INTERNER.intern_str(host));
+        target(config, host);
+    }
+
+    fn target_config<'a>(config: &'a mut Config, target: &str) -> &'a mut Target {
+        config.target_config.entry(INTERNER.intern_str(target)).or_insert(Default::default())
+    }
+
+    fn external_llvm(config: &mut Config) {
+        let llvm_config = PathBuf::from("/fake/llvm/bin/llvm-config");
+        target_config(config, HOST).llvm_config = Some(llvm_config);
+    }
+
+    /// Runs the check `name` against a configuration building for and on
+    /// `HOST`, as changed by `setup`, where only `tools` can be found. The
+    /// detection `check` does first isn't run.
+    fn outcome<F>(name: &str, setup: F, tools: &[(&'static str, &'static str)]) -> Outcome
+        where F: FnOnce(&mut Config)
+    {
+        let mut config = Config::default();
+        config.build = INTERNER.intern_str(HOST);
+        config.hosts = vec![config.build];
+        config.targets = vec![config.build];
+        config.ignore_git = true;
+        setup(&mut config);
+        // Give every target a `[target]` section, as if config.toml had an
+        // empty one.
+        for target in config.targets.clone() {
+            config.target_config.entry(target).or_insert(Default::default());
+        }
+        let mut build = Build::bare(config);
+        let resolver = CannedResolver(tools.iter().cloned().collect());
+        let finder = Finder::with_resolver(Box::new(resolver));
+        let warnings = Warnings::new(Vec::new(), true);
+        let mut sanity = Sanity { build: &mut build, finder, warnings };
+        let c = CHECKS.iter().find(|c| c.name == name).expect("unknown check");
+        match run_check(&mut sanity, c).and_then(|()| sanity.finder.finish()) {
+            Ok(()) if sanity.warnings.issued > 0 => Outcome::Warned,
+            Ok(()) => Outcome::Passed,
+            Err(e) => Outcome::Failed(e.kind),
+        }
+    }
+
+    #[test]
+    fn cmake_is_needed_to_build_llvm() {
+        assert_eq!(outcome("cmake", |_| {}, &[("cmake", "/fake/bin/cmake")]), Outcome::Passed);
+        assert_eq!(outcome("cmake", |_| {}, &[]), Outcome::Failed(ErrorKind::MissingTool));
+        assert_eq!(outcome("cmake", external_llvm, &[]), Outcome::Passed);
+    }
+
+    #[test]
+    fn ninja_build_is_ninja() {
+        let ninja = |config: &mut Config| config.ninja = true;
+        assert_eq!(outcome("ninja", ninja, &[("ninja-build", "/fake/bin/ninja-build")]),
+                   Outcome::Passed);
+        assert_eq!(outcome("ninja", ninja, &[]), Outcome::Failed(ErrorKind::MissingTool));
+    }
+
+    #[test]
+    fn configured_ccache_is_needed() {
+        let ccache = |config: &mut Config| config.ccache = Some("ccache".to_string());
+        assert_eq!(outcome("ccache", ccache, &[]), Outcome::Failed(ErrorKind::MissingTool));
+    }
+
+    #[test]
+    fn sanitizers_with_ccache() {
+        let setup = |config: &mut Config| {
+            config.ccache = Some("ccache".to_string());
+            config.sanitizers = true;
+        };
+        assert_eq!(outcome("ccache-sanitizers", setup, &[("ccache", "/fake/bin/ccache")]),
+                   Outcome::Warned);
+    }
+
+    #[test]
+    fn llvm_profdata_of_external_llvm() {
+        let setup = |config: &mut Config| {
+            external_llvm(config);
+            config.profiler = true;
+        };
+        assert_eq!(outcome("llvm-profdata", setup, &[]), Outcome::Warned);
+        assert_eq!(outcome("llvm-profdata", setup,
+                           &[("llvm-profdata", "/fake/bin/llvm-profdata")]),
+                   Outcome::Passed);
+    }
+
+    #[test]
+    fn jemalloc_on_some_hosts() {
+        let setup = |config: &mut Config| {
+            config.use_jemalloc = true;
+            host(config, "x86_64-pc-windows-msvc");
+        };
+        assert_eq!(outcome("jemalloc-hosts", setup, &[]), Outcome::Warned);
+    }
+
+    #[test]
+    fn llvm_assertions_without_codegen_tests() {
+        let assertions = |config: &mut Config| config.llvm_assertions = true;
+        assert_eq!(outcome("llvm-assertions-tests", assertions, &[]), Outcome::Warned);
+        let setup = |config: &mut Config| {
+            config.llvm_assertions = true;
+            config.codegen_tests = true;
+        };
+        assert_eq!(outcome("llvm-assertions-tests", setup, &[]), Outcome::Passed);
+    }
+
+    #[test]
+    fn test_emulators() {
+        let setup = |config: &mut Config| {
+            config.cmd = Subcommand::Bench { paths: Vec::new(), test_args: Vec::new() };
+            target(config, "arm-unknown-linux-gnueabihf");
+            target_config(config, "arm-unknown-linux-gnueabihf").qemu_rootfs =
+                Some(env::temp_dir());
+        };
+        assert_eq!(outcome("test-emulators", setup, &[("cpio", "/fake/bin/cpio")]),
+                   Outcome::Failed(ErrorKind::MissingTool));
+        assert_eq!(outcome("test-emulators", setup,
+                           &[("cpio", "/fake/bin/cpio"),
+                             ("qemu-system-arm", "/fake/bin/qemu-system-arm")]),
+                   Outcome::Passed);
+    }
+
+    #[test]
+    fn archive_tools() {
+        let distcheck = |config: &mut Config| {
+            config.cmd = Subcommand::Test {
+                paths: vec![PathBuf::from("distcheck")],
+                bless: false,
+                compare_mode: None,
+                test_args: Vec::new(),
+                rustc_args: Vec::new(),
+                fail_fast: true,
+                doc_tests: DocTests::Yes,
+            };
+        };
+        assert_eq!(outcome("archive-tools", distcheck, &[]),
+                   Outcome::Failed(ErrorKind::MissingTool));
+        assert_eq!(outcome("archive-tools", distcheck, &[("tar", "/fake/bin/tar")]),
+                   Outcome::Failed(ErrorKind::MissingTool));
+        assert_eq!(outcome("archive-tools", distcheck,
+                           &[("tar", "/fake/bin/tar"), ("gzip", "/fake/bin/gzip")]),
+                   Outcome::Passed);
+
+        let static_openssl = |config: &mut Config| {
+            config.cmd = Subcommand::Dist { paths: Vec::new() };
+            config.extended = true;
+            config.openssl_static = true;
+        };
+        assert_eq!(outcome("archive-tools", static_openssl, &[]),
+                   Outcome::Failed(ErrorKind::MissingTool));
+        let dist = |config: &mut Config| config.cmd = Subcommand::Dist { paths: Vec::new() };
+        assert_eq!(outcome("archive-tools", dist, &[]), Outcome::Passed);
+    }
+
+    #[test]
+    fn ios_target_on_linux() {
+        let ios = |config: &mut Config| target(config, "aarch64-apple-ios");
+        assert_eq!(outcome("ios-host", ios, &[]), Outcome::Failed(ErrorKind::Config));
+    }
+
+    #[test]
+    fn sdk_target_without_sdk() {
+        let redox = |config: &mut Config| target(config, "x86_64-unknown-redox");
+        assert_eq!(outcome("sdk-targets", redox, &[]), Outcome::Passed);
+    }
+
+    #[test]
+    fn none_targets_are_no_std() {
+        let none = |config: &mut Config| target(config, "thumbv7em-none-eabihf");
+        assert_eq!(outcome("no-std-targets", none, &[]), Outcome::Failed(ErrorKind::Config));
+        let no_std = |config: &mut Config| {
+            target(config, "thumbv7em-none-eabihf");
+            target_config(config, "thumbv7em-none-eabihf").no_std = true;
+        };
+        assert_eq!(outcome("no-std-targets", no_std, &[]), Outcome::Passed);
+    }
+
+    #[test]
+    fn denied_warnings_are_promoted() {
+        let mut warnings = Warnings::new(vec!["cc-max-version".to_string()], true);
+        warnings.check = "sanitizer-runtimes";
+        warnings.warn("not promoted".to_string());
+        assert!(warnings.promoted.is_empty());
+        warnings.check = "cc-max-version";
+        warnings.warn("promoted".to_string());
+        assert_eq!(warnings.promoted, ["promoted"]);
+        assert_eq!(warnings.issued, 2);
+    }
+
+    #[test]
+    fn llvm_releases() {
+        assert_eq!(llvm_release("LLVM (http://llvm.org/):\n  LLVM version 6.0.0\n  \
+                                 Optimized build.\n"),
+                   Some((6, 0)));
+        assert_eq!(llvm_release("7.0.0svn"), Some((7, 0)));
+        assert_eq!(llvm_release("LLVM version 3.9.1"), Some((3, 9)));
+        assert_eq!(llvm_release("LLVM (http://llvm.org/):"), None);
+    }
+
+    #[test]
+    fn identical_warnings_are_merged() {
+        let mut warnings = Warnings::new(Vec::new(), true);
+        warnings.warn("gdb is too old".to_string());
+        warnings.note("gdb is too old".to_string());
+        warnings.warn("gdb is too old".to_string());
+        warnings.warn("cmake is too old".to_string());
+        assert_eq!(warnings.issued, 4);
+        assert_eq!(warnings.pending, [("warning: gdb is too old".to_string(), 2),
+                                      ("note: gdb is too old".to_string(), 1),
+                                      ("warning: cmake is too old".to_string(), 1)]);
+        warnings.flush();
+        assert!(warnings.pending.is_empty());
+    }
+
+    #[test]
+    fn temp_dir_from_environment() {
+        let var = TEMP_DIR_VARS[0];
+        let (dir, source) = resolve_temp_dir(&|name| {
+            if name == var { Some(OsString::from("/scratch")) } else { None }
+        });
+        assert_eq!(dir, PathBuf::from("/scratch"));
+        assert_eq!(source, format!("${}", var));
+
+        // Empty variables are skipped, like `env::temp_dir` does.
+        let (_, source) = resolve_temp_dir(&|_| Some(OsString::new()));
+        assert!(!source.starts_with('$'));
+    }
+
+    #[test]
+    fn store_stub_paths() {
+        assert!(in_windows_apps(Path::new(
+            r"C:\Users\me\AppData\Local\Microsoft\WindowsApps\python.exe")));
+        assert!(in_windows_apps(Path::new(
+            r"c:\users\me\appdata\local\microsoft\windowsapps\python")));
+        assert!(!in_windows_apps(Path::new(r"C:\Python27\python.exe")));
+        assert!(!in_windows_apps(Path::new("/usr/bin/python2.7")));
+        assert!(!in_windows_apps(Path::new(r"C:\NotWindowsApps\python.exe")));
+    }
+
+    #[test]
+    fn target_object_formats() {
+        assert_eq!(object_format("x86_64-unknown-linux-gnu"), "ELF");
+        assert_eq!(object_format("thumbv7em-none-eabihf"), "ELF");
+        assert_eq!(object_format("x86_64-apple-darwin"), "Mach-O");
+        assert_eq!(object_format("aarch64-apple-ios"), "Mach-O");
+        assert_eq!(object_format("x86_64-pc-windows-msvc"), "COFF");
+        assert_eq!(object_format("i686-pc-windows-gnu"), "COFF");
+        assert_eq!(object_format("wasm32-unknown-unknown"), "wasm");
+    }
+
+    #[test]
+    fn known_linkers() {
+        let format = |name: &str| known_linker_format(Path::new(name));
+        assert_eq!(format("link.exe"), Some("COFF"));
+        assert_eq!(format("/usr/bin/x86_64-w64-mingw32-gcc"), Some("COFF"));
+        assert_eq!(format("ld64.lld"), Some("Mach-O"));
+        assert_eq!(format("x86_64-apple-darwin15-clang"), Some("Mach-O"));
+        assert_eq!(format("wasm-ld"), Some("wasm"));
+        assert_eq!(format("ld.lld"), Some("ELF"));
+        assert_eq!(format("arm-linux-gnueabihf-gcc"), Some("ELF"));
+        assert_eq!(format("aarch64-linux-gnu-gcc"), Some("ELF"));
+        assert_eq!(format("cc"), None);
+        assert_eq!(format("clang"), None);
+    }
+
+    #[test]
+    fn bfd_targets() {
+        let line = "ld: supported targets: elf64-x86-64 elf32-i386 elf32-iamcu \
+                    pei-i386 pei-x86-64 elf64-little srec symbolsrec binary";
+        assert_eq!(bfd_target_formats(line), ["ELF", "COFF"]);
+        assert!(bfd_target_formats("ld: supported targets: binary").is_empty());
+    }
+
+    #[test]
+    fn prepended_dirs_are_searched_first() {
+        let dir = scratch_dir("prepended_dirs_are_searched_first");
+        let (vendored, system) = (dir.join("vendor"), dir.join("system"));
+        t!(fs::create_dir_all(&vendored));
+        t!(fs::create_dir_all(&system));
+        touch(&vendored.join("cmake"));
+        touch(&system.join("cmake"));
+        touch(&system.join("ninja"));
+
+        let mut resolver = PathResolver::with_path(t!(env::join_paths(&[&system])));
+        assert_eq!(resolver.resolve(OsStr::new("cmake")), Some(system.join("cmake")));
+        resolver.prepend(vendored.clone(), "build.tools-bin");
+        assert_eq!(resolver.resolve(OsStr::new("cmake")), Some(vendored.join("cmake")));
+        assert_eq!(resolver.resolve(OsStr::new("ninja")), Some(system.join("ninja")));
+    }
+
+    #[test]
+    fn musl_crt_objects() {
+        let dir = scratch_dir("musl_crt_objects");
+        let flat = dir.join("flat");
+        let multiarch = dir.join("multiarch");
+        t!(fs::create_dir_all(&flat));
+        t!(fs::create_dir_all(multiarch.join("arm-linux-musleabihf")));
+        touch(&flat.join("crt1.o"));
+        touch(&multiarch.join("arm-linux-musleabihf/crti.o"));
+
+        let target = "x86_64-unknown-linux-musl";
+        assert_eq!(find_crt_object(&flat, target, "crt1.o"), Some(flat.join("crt1.o")));
+        assert_eq!(find_crt_object(&flat, target, "crti.o"), None);
+        assert_eq!(find_crt_object(&multiarch, "arm-unknown-linux-musleabihf", "crti.o"),
+                   Some(multiarch.join("arm-linux-musleabihf/crti.o")));
+        assert_eq!(find_crt_object(&multiarch, target, "crti.o"), None);
+    }
+
+    #[test]
+    fn gcc_cross_without_ar() {
+        let arm = "arm-unknown-linux-gnueabihf";
+        let cc = Path::new("/usr/bin/arm-linux-gnueabihf-gcc");
+        assert_eq!(expected_cross_ar(arm, cc, Path::new("ar")),
+                   Some("arm-linux-gnueabihf-ar".to_string()));
+        assert_eq!(expected_cross_ar(arm, cc, Path::new("/usr/bin/ar")),
+                   Some("arm-linux-gnueabihf-ar".to_string()));
+        assert_eq!(expected_cross_ar("aarch64-unknown-linux-gnu",
+                                     Path::new("aarch64-linux-gnu-gcc-7"), Path::new("ar")),
+                   Some("aarch64-linux-gnu-ar".to_string()));
+        assert_eq!(expected_cross_ar(arm, cc, Path::new("/usr/bin/arm-linux-gnueabihf-ar")),
+                   None);
+        // Native compilers and clang can use the host's `ar` just fine.
+        assert_eq!(expected_cross_ar(arm, Path::new("gcc"), Path::new("ar")), None);
+        assert_eq!(expected_cross_ar(arm, Path::new("clang"), Path::new("ar")), None);
+        assert_eq!(expected_cross_ar(arm, Path::new("arm-linux-gnueabihf-clang"),
+                                     Path::new("ar")),
+                   None);
+    }
+
+    #[test]
+    fn musl_cross_with_host_ar() {
+        // As dist-various-1 builds these, leaving `ar` to `cc2ar`.
+        assert_eq!(expected_cross_ar("mipsel-unknown-linux-musl",
+                                     Path::new("mipsel-openwrt-linux-gcc"), Path::new("ar")),
+                   None);
+        assert_eq!(expected_cross_ar("armv5te-unknown-linux-musleabi",
+                                     Path::new("arm-linux-gnueabi-gcc"), Path::new("ar")),
+                   None);
+        assert_eq!(expected_cross_ar("x86_64-unknown-openbsd",
+                                     Path::new("x86_64-unknown-openbsd-gcc"), Path::new("ar")),
+                   None);
+    }
+
+    #[test]
+    fn missing_cxx_headers() {
+        let gcc = "probe.cpp:1:10: fatal error: memory: No such file or directory\n \
+                   #include <memory>\n          ^~~~~~~~\ncompilation terminated.";
+        assert_eq!(missing_header(gcc), Some("memory"));
+        assert_eq!(missing_header("probe.cpp:3:10: fatal error: 'vector' file not found"),
+                   Some("vector"));
+        assert_eq!(missing_header("probe.cpp(4): fatal error C1083: Cannot open include \
+                                   file: 'atomic': No such file or directory"),
+                   Some("atomic"));
+        assert_eq!(missing_header("cc1plus: out of memory allocating 4072 bytes"), None);
+    }
+
+    #[test]
+    fn global_compilers() {
+        let linux = INTERNER.intern_str("x86_64-unknown-linux-gnu");
+        let arm = INTERNER.intern_str("armv7-unknown-linux-gnueabihf");
+        let musl = INTERNER.intern_str("x86_64-unknown-linux-musl");
+        let none = |_: &str| None;
+        let unconfigured = |_: Interned<String>| false;
+        assert_eq!(global_compiler_targets("CC", &[linux, arm, musl], &unconfigured, &none),
+                   [linux, arm, musl]);
+        let arm_configured = |target: Interned<String>| target == arm;
+        assert_eq!(global_compiler_targets("CC", &[linux, arm, musl], &arm_configured, &none),
+                   [linux, musl]);
+        let musl_env = |name: &str| {
+            if name == "CC_x86_64_unknown_linux_musl" { Some(OsString::from("musl-gcc")) }
+            else { None }
+        };
+        assert_eq!(global_compiler_targets("CC", &[linux, arm, musl], &unconfigured, &musl_env),
+                   [linux, arm]);
+        assert_eq!(global_compiler_targets("CXX", &[musl], &unconfigured, &musl_env), [musl]);
+    }
+
+    #[test]
+    fn mixed_jemalloc_hosts() {
+        let hosts = ["x86_64-unknown-linux-gnu", "x86_64-pc-windows-msvc",
+                     "i686-pc-windows-gnu", "i686-pc-windows-msvc"];
+        assert_eq!(jemalloc_hosts(&hosts),
+                   (vec!["x86_64-unknown-linux-gnu", "i686-pc-windows-gnu"],
+                    vec!["x86_64-pc-windows-msvc", "i686-pc-windows-msvc"]));
+        assert_eq!(jemalloc_hosts(&hosts[..1]), (vec![hosts[0]], vec![]));
+    }
+
+    #[test]
+    fn proc_macro_hosts() {
+        assert_eq!(target_env("x86_64-unknown-linux-gnu"), "gnu");
+        assert_eq!(target_env("armv7-unknown-linux-musleabihf"), "musleabihf");
+        assert_eq!(target_env("x86_64-apple-darwin"), "");
+    }
+
+    #[test]
+    fn crt_static_conflicts() {
+        assert!(crt_static_conflict("x86_64-pc-windows-msvc", false).is_some());
+        assert!(crt_static_conflict("x86_64-pc-windows-msvc", true).is_none());
+        assert!(crt_static_conflict("x86_64-unknown-linux-gnu", true).is_some());
+        assert!(crt_static_conflict("x86_64-apple-darwin", true).is_some());
+        // musl can go either way, the latter for proc-macros.
+        assert!(crt_static_conflict("x86_64-unknown-linux-musl", true).is_none());
+        assert!(crt_static_conflict("x86_64-unknown-linux-musl", false).is_none());
+        assert!(crt_static_conflict("arm-unknown-linux-musleabihf", false).is_none());
+        assert!(proc_macros_unsupported("x86_64-unknown-linux-musl", None));
+        assert!(proc_macros_unsupported("x86_64-unknown-linux-musl", Some(true)));
+        assert!(!proc_macros_unsupported("x86_64-unknown-linux-musl", Some(false)));
+        assert!(proc_macros_unsupported("x86_64-unknown-linux-gnu", Some(true)));
+        assert!(!proc_macros_unsupported("x86_64-unknown-linux-gnu", None));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn executable_bit() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch_dir("executable_bit");
+        let tool = dir.join("tool");
+        touch(&tool);
+        t!(fs::set_permissions(&tool, fs::Permissions::from_mode(0o644)));
+        let problem = executable_problem(&tool).unwrap();
+        assert!(problem.contains("644"), "{}", problem);
+        t!(fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)));
+        assert_eq!(executable_problem(&tool), None);
+    }
+
+    #[test]
+    fn cmake_generators() {
+        let mut config = Config::default();
+        config.build = INTERNER.intern_str("x86_64-pc-windows-msvc");
+        assert_eq!(cmake_generator(&config, "x86_64-pc-windows-msvc"),
+                   CmakeGenerator::VisualStudio);
+        config.ninja = true;
+        assert_eq!(cmake_generator(&config, "x86_64-pc-windows-msvc"), CmakeGenerator::Ninja);
+
+        config.build = INTERNER.intern_str("x86_64-unknown-linux-gnu");
+        assert_eq!(cmake_generator(&config, "x86_64-unknown-linux-gnu"), CmakeGenerator::Ninja);
+        config.ninja = false;
+        assert_eq!(cmake_generator(&config, "x86_64-unknown-linux-gnu"),
+                   CmakeGenerator::UnixMakefiles);
+    }
+
+    #[test]
+    fn released_stage0() {
+        let dir = scratch_dir("released_stage0");
+        let stage0 = dir.join("stage0.txt");
+        t!(t!(File::create(&stage0)).write_all(b"date: 2018-06-05\nrustc: 1.26.2\n"));
+        check_released_stage0(&stage0).unwrap();
+    }
+
+    #[test]
+    fn dev_stage0() {
+        let dir = scratch_dir("dev_stage0");
+        let stage0 = dir.join("stage0.txt");
+        t!(t!(File::create(&stage0)).write_all(b"date: 2018-06-05\ndev: 1\n"));
+        let err = check_released_stage0(&stage0).unwrap_err();
+        assert!(err.message.contains("bootstrapping from a dev compiler"), "{}", err);
+    }
+
+    #[test]
+    fn missing_stage0() {
+        let err = check_released_stage0(&scratch_dir("missing_stage0").join("stage0.txt"))
+            .unwrap_err();
+        assert!(err.message.contains("doesn't exist"), "{}", err);
+    }
+
+    #[test]
+    fn apple_sdks() {
+        assert_eq!(apple_sdk("x86_64-apple-darwin"), Some(("macosx", (10, 7, 0))));
+        assert_eq!(apple_sdk("aarch64-apple-ios"), Some(("iphoneos", (7, 0, 0))));
+        assert_eq!(apple_sdk("armv7s-apple-ios"), Some(("iphoneos", (7, 0, 0))));
+        assert_eq!(apple_sdk("x86_64-apple-ios"), Some(("iphonesimulator", (7, 0, 0))));
+        assert_eq!(apple_sdk("i386-apple-ios"), Some(("iphonesimulator", (7, 0, 0))));
+        assert_eq!(apple_sdk("x86_64-unknown-linux-gnu"), None);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn broken_symlinks_are_remembered() {
+        use std::os::unix::fs::symlink;
+
+        let dir = scratch_dir("broken_symlinks_are_remembered");
+        t!(symlink(dir.join("missing"), dir.join("cmake")));
+        let mut resolver = PathResolver::with_path(t!(env::join_paths(&[&dir])));
+        assert_eq!(resolver.resolve(OsStr::new("cmake")), None);
+        assert_eq!(resolver.explain_missing(OsStr::new("cmake")),
+                   Some(format!("found a broken symlink at {} pointing to {}",
+                                dir.join("cmake").display(), dir.join("missing").display())));
+        assert_eq!(resolver.resolve(OsStr::new("ninja")), None);
+        assert_eq!(resolver.explain_missing(OsStr::new("ninja")), None);
+    }
+
+    #[test]
+    fn target_endianness() {
+        assert_eq!(target_endian("powerpc-unknown-linux-gnu"), Endian::Big);
+        assert_eq!(target_endian("powerpc64-unknown-linux-gnu"), Endian::Big);
+        assert_eq!(target_endian("powerpc64le-unknown-linux-gnu"), Endian::Little);
+        assert_eq!(target_endian("mips-unknown-linux-musl"), Endian::Big);
+        assert_eq!(target_endian("mipsel-unknown-linux-musl"), Endian::Little);
+        assert_eq!(target_endian("s390x-unknown-linux-gnu"), Endian::Big);
+        assert_eq!(target_endian("armebv7r-none-eabihf"), Endian::Big);
+        assert_eq!(target_endian("armv7-unknown-linux-musleabihf"), Endian::Little);
+        assert_eq!(target_endian("x86_64-unknown-linux-musl"), Endian::Little);
+    }
+
+    #[test]
+    fn elf_endianness() {
+        let dir = scratch_dir("elf_endianness");
+        let write = |name: &str, bytes: &[u8]| {
+            let path = dir.join(name);
+            t!(t!(File::create(&path)).write_all(bytes));
+            path
+        };
+        let little = write("little.o", b"\x7fELF\x01\x01\x01\0");
+        let big = write("big.o", b"\x7fELF\x01\x02\x01\0");
+        let archive = write("libc.a", b"!<arch>\n");
+        assert_eq!(elf_endian(&little), Some(Endian::Little));
+        assert_eq!(elf_endian(&big), Some(Endian::Big));
+        assert_eq!(elf_endian(&archive), None);
+    }
+
+    #[test]
+    fn cmake_cache_entries() {
+        let cache = "\
+# This is the CMakeCache file.
+//C compiler
+CMAKE_C_COMPILER:FILEPATH=/usr/bin/gcc-7
+CMAKE_C_COMPILER_ARG1:UNINITIALIZED=clang
+//Flags used by the compiler: a=b
+CMAKE_C_FLAGS:STRING=-ffunction-sections -fdata-sections
+";
+        assert_eq!(cmake_cache_entry(cache, "CMAKE_C_COMPILER"), Some("/usr/bin/gcc-7"));
+        assert_eq!(cmake_cache_entry(cache, "CMAKE_C_COMPILER_ARG1"), Some("clang"));
+        assert_eq!(cmake_cache_entry(cache, "CMAKE_C_FLAGS"),
+                   Some("-ffunction-sections -fdata-sections"));
+        assert_eq!(cmake_cache_entry(cache, "CMAKE_CXX_COMPILER"), None);
+    }
+
+    #[test]
+    fn moved_cmake_sources() {
+        let dir = scratch_dir("moved_cmake_sources");
+        let llvm = dir.join("src/llvm");
+        t!(fs::create_dir_all(&llvm));
+        assert!(same_dir(&llvm, &dir.join("src/./llvm")));
+        assert!(!same_dir(&llvm, &dir.join("src")));
+        assert!(!same_dir(&dir.join("old/src/llvm"), &llvm));
+    }
+
+    #[test]
+    fn sdk_targets() {
+        assert!(sdk_target("x86_64-unknown-linux-gnu").is_none());
+        let sdk = sdk_target("aarch64-unknown-fuchsia").unwrap();
+        assert_eq!(sdk.name, "Fuchsia");
+        assert_eq!(sdk_required_paths(sdk, "aarch64-unknown-fuchsia"),
+                   ["arch/arm64/sysroot/include", "arch/arm64/sysroot/lib", "tools"]);
+        assert_eq!(sdk_required_paths(sdk, "x86_64-unknown-fuchsia")[0],
+                   "arch/x64/sysroot/include");
+        let sdk = sdk_target("x86_64-unknown-haiku").unwrap();
+        assert!(sdk.env.is_none());
+        assert_eq!(sdk_required_paths(sdk, "i686-unknown-haiku")[1], "boot/system/develop/lib");
+        assert_eq!(sdk_target("x86_64-unknown-redox").unwrap().name, "Redox");
+    }
+
+    thread_local! {
+        /// What `probe` returns instead of running a command, keyed by the
+        /// command's `Debug` output.
+        static CANNED_OUTPUTS: RefCell<HashMap<String, Output>> = RefCell::new(HashMap::new());
+    }
+
+    pub fn canned_output(cmd: &Command) -> Option<Output> {
+        CANNED_OUTPUTS.with(|outputs| outputs.borrow().get(&format!("{:?}", cmd)).cloned())
+    }
+
+    /// Makes `probe` return `stdout` and `stderr` with the exit code `code`
+    /// for `cmd`, which is written like `"git" "--version"`.
+    fn can(cmd: &str, code: i32, stdout: &str, stderr: &str) {
+        let output = Output {
+            status: exit_status(code),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: stderr.as_bytes().to_vec(),
+        };
+        CANNED_OUTPUTS.with(|outputs| outputs.borrow_mut().insert(cmd.to_string(), output));
+    }
+
+    #[cfg(unix)]
+    fn exit_status(code: i32) -> process::ExitStatus {
+        use std::os::unix::process::ExitStatusExt;
+        process::ExitStatus::from_raw(code << 8)
+    }
+
+    #[cfg(windows)]
+    fn exit_status(code: i32) -> process::ExitStatus {
+        use std::os::windows::process::ExitStatusExt;
+        process::ExitStatus::from_raw(code as u32)
+    }
+
+    /// A filesystem containing only `files` and the dangling symlinks
+    /// `broken`, optionally ignoring case like Windows and macOS do.
+    #[derive(Default)]
+    struct VirtualFs {
+        files: Vec<&'static str>,
+        broken: Vec<(&'static str, &'static str)>,
+        ignore_case: bool,
+    }
+
+    impl VirtualFs {
+        fn same(&self, a: &Path, b: &str) -> bool {
+            if self.ignore_case {
+                a.to_string_lossy().eq_ignore_ascii_case(b)
+            } else {
+                a == Path::new(b)
+            }
+        }
+    }
+
+    impl PathFs for VirtualFs {
+        fn is_file(&self, path: &Path) -> bool {
+            self.files.iter().any(|file| self.same(path, file))
+        }
+
+        fn exists(&self, path: &Path) -> bool {
+            self.is_file(path)
+        }
+
+        fn broken_symlink(&self, path: &Path) -> Option<PathBuf> {
+            self.broken.iter().find(|&&(link, _)| self.same(path, link))
+                .map(|&(_, to)| PathBuf::from(to))
+        }
+    }
+
+    fn virtual_resolver(fs: VirtualFs, dirs: &[&str]) -> PathResolver<VirtualFs> {
+        PathResolver::with_fs(fs, t!(env::join_paths(dirs)))
+    }
+
+    fn resolve(resolver: &mut ToolResolver, name: &str) -> Option<PathBuf> {
+        resolver.resolve(OsStr::new(name))
+    }
+
+    #[test]
+    fn virtual_path_exe_extension() {
+        let fs = VirtualFs {
+            files: vec!["/tools/ninja.exe", "/tools/cmake.bat"],
+            ..Default::default()
+        };
+        let mut resolver = virtual_resolver(fs, &["/tools"]);
+        // The name is handed back without the extension.
+        assert_eq!(resolve(&mut resolver, "ninja"), Some(PathBuf::from("/tools/ninja")));
+        // Only `.exe` is tried, whatever PATHEXT says.
+        assert_eq!(resolve(&mut resolver, "cmake"), None);
+    }
+
+    #[test]
+    fn virtual_path_duplicates() {
+        let fs = VirtualFs { files: vec!["/a/git", "/b/git"], ..Default::default() };
+        let mut resolver = virtual_resolver(fs, &["/b", "/a", "/b"]);
+        assert_eq!(resolve(&mut resolver, "git"), Some(PathBuf::from("/b/git")));
+        assert_eq!(resolve(&mut resolver, "cmake"), None);
+    }
+
+    #[test]
+    fn virtual_path_ignoring_case() {
+        let fs = VirtualFs {
+            files: vec!["/Tools/Git.exe"],
+            ignore_case: true,
+            ..Default::default()
+        };
+        let mut resolver = virtual_resolver(fs, &["/tools"]);
+        // The path is spelled the way it was asked for, not how it's stored.
+        assert_eq!(resolve(&mut resolver, "git"), Some(PathBuf::from("/tools/git")));
+        let fs = VirtualFs { files: vec!["/Tools/Git.exe"], ..Default::default() };
+        assert_eq!(resolve(&mut virtual_resolver(fs, &["/tools"]), "git"), None);
+    }
+
+    #[test]
+    fn virtual_path_broken_symlinks() {
+        let fs = VirtualFs {
+            broken: vec![("/a/python", "/opt/python/bin/python2"), ("/b/python", "/gone")],
+            ..Default::default()
+        };
+        let mut resolver = virtual_resolver(fs, &["/a", "/b"]);
+        assert_eq!(resolve(&mut resolver, "python"), None);
+        let why = resolver.explain_missing(OsStr::new("python")).unwrap();
+        // The first broken link is the one explained.
+        assert!(why.contains("/opt/python/bin/python2"), "{}", why);
+
+        let fs = VirtualFs {
+            files: vec!["/b/python"],
+            broken: vec![("/a/python", "/opt/python/bin/python2")],
+            ..Default::default()
+        };
+        let mut resolver = virtual_resolver(fs, &["/a", "/b"]);
+        assert_eq!(resolve(&mut resolver, "python"), Some(PathBuf::from("/b/python")));
+    }
+
+    #[test]
+    fn canned_git_outputs() {
+        can("\"/virtual/git\" \"--exec-path\"", 0, "/virtual/libexec/git-core\n", "");
+        can("\"/virtual/git\" \"submodule\" \"-h\"", 129,
+            "usage: git submodule [--quiet] [--cached]\n", "");
+        assert!(git_has_submodule(Path::new("/virtual/git")));
+        can("\"/virtual/git\" \"submodule\" \"-h\"", 1,
+            "", "git: 'submodule' is not a git command. See 'git --help'.\n");
+        assert!(!git_has_submodule(Path::new("/virtual/git")));
+    }
+
+    #[test]
+    fn custom_tool_resolver() {
+        let mut tools = HashMap::new();
+        tools.insert("cmake", "/opt/toolchain/cmake-3.11/bin/cmake");
+        tools.insert("python2.7", "/opt/toolchain/python/bin/python2.7");
+        let mut finder = Finder::with_resolver(Box::new(CannedResolver(tools)));
+        assert_eq!(finder.must_have("cmake").unwrap(),
+                   PathBuf::from("/opt/toolchain/cmake-3.11/bin/cmake"));
+        assert_eq!(finder.first_of(&["python3", "python2.7"]),
+                   Some(PathBuf::from("/opt/toolchain/python/bin/python2.7")));
+        assert_eq!(finder.maybe_have("ninja"), None);
+    }
+
+    #[test]
+    fn tool_cache_is_read_back() {
+        let dir = scratch_dir("tool_cache_is_read_back");
+        let bin = dir.join("bin");
+        t!(fs::create_dir_all(&bin));
+        touch(&bin.join("cmake"));
+        let file = dir.join(TOOL_CACHE_FILE);
+
+        let mut finder = Finder::with_path(bin.clone().into_os_string());
+        assert_eq!(finder.maybe_have("cmake"), Some(bin.join("cmake")));
+        assert_eq!(finder.maybe_have("ninja"), None);
+        t!(finder.save_cache(&file, "key"));
+
+        // Nothing is searched for in an empty `PATH`, so only the cache can
+        // find cmake.
+        let mut finder = Finder::with_path(OsString::new());
+        assert_eq!(finder.load_cache(&file, "key"), 1);
+        assert_eq!(finder.maybe_have("cmake"), Some(bin.join("cmake")));
+        assert_eq!(finder.maybe_have("ninja"), None);
+
+        let mut finder = Finder::with_path(OsString::new());
+        assert_eq!(finder.load_cache(&file, "other key"), 0);
+        assert_eq!(finder.maybe_have("cmake"), None);
+
+        t!(fs::remove_file(bin.join("cmake")));
+        let mut finder = Finder::with_path(OsString::new());
+        assert_eq!(finder.load_cache(&file, "key"), 0);
+        assert_eq!(finder.load_cache(&dir.join("missing"), "key"), 0);
+    }
+
+    #[test]
+    fn missing_command_error() {
+        let dir = scratch_dir("missing_command_error");
+        let mut finder = Finder::with_path(dir.into_os_string());
+        let err = finder.must_have("cmake").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::MissingTool);
+        assert_eq!(err.subject, Some("cmake".to_string()));
+        assert_eq!(err.to_string(), "\n\ncouldn't find required command: \"cmake\"\n\n");
+    }
+
+    #[test]
+    fn missing_commands_are_reported_together() {
+        let mut tools = HashMap::new();
+        tools.insert("cmake", "/opt/toolchain/bin/cmake");
+        let mut finder = Finder::with_resolver(Box::new(CannedResolver(tools)));
+        assert!(finder.finish().is_ok());
+        assert_eq!(finder.require("cmake").unwrap(),
+                   Some(PathBuf::from("/opt/toolchain/bin/cmake")));
+        assert_eq!(finder.require("ninja").unwrap(), None);
+        assert_eq!(finder.require("cc").unwrap(), None);
+        assert_eq!(finder.require("ninja").unwrap(), None);
+        let err = finder.finish().unwrap_err();
+        assert_eq!(err.kind, ErrorKind::MissingTool);
+        assert_eq!(err.subject, None);
+        assert_eq!(err.to_string(), "\n\ncouldn't find 2 required commands:\n\n    \"ninja\"\n    \
+                                     \"cc\"\n\n");
+    }
+
+    #[test]
+    fn install_hints() {
+        let hint = install_hint("cmake", "x86_64-unknown-linux-gnu").unwrap();
+        assert_eq!(hint, "cmake can be installed with `apt install cmake` on Debian and Ubuntu, \
+                          `dnf install cmake` on Fedora or `pacman -S cmake` on Arch.");
+        assert_eq!(install_hint("ninja", "x86_64-apple-darwin").unwrap(),
+                   "ninja can be installed with `brew install ninja` with Homebrew.");
+        assert!(install_hint("c++", "aarch64-unknown-linux-gnu").unwrap()
+                    .contains("`dnf install gcc-c++`"));
+        assert_eq!(install_hint("cc", "x86_64-apple-darwin"), None);
+        assert_eq!(install_hint("cmake", "x86_64-pc-windows-msvc"), None);
+        assert_eq!(install_hint("x86_64-linux-musl-gcc", "x86_64-unknown-linux-gnu"), None);
+
+        let mut finder = Finder::with_resolver(Box::new(CannedResolver(HashMap::new())));
+        finder.build = Some(INTERNER.intern_str("x86_64-unknown-linux-gnu"));
+        let err = finder.must_have("/usr/local/bin/git").unwrap_err();
+        assert!(err.to_string().contains("`apt install git`"));
+    }
+
+    #[test]
+    fn one_missing_command_is_reported_like_must_have() {
+        let dir = scratch_dir("one_missing_command_is_reported_like_must_have");
+        let mut finder = Finder::with_path(dir.into_os_string());
+        assert_eq!(finder.require("cmake").unwrap(), None);
+        let err = finder.finish().unwrap_err();
+        assert_eq!(err.subject, Some("cmake".to_string()));
+        assert_eq!(err.to_string(), "\n\ncouldn't find required command: \"cmake\"\n\n");
+    }
+
+    #[test]
+    fn shell_alias_hints() {
+        let env = |vars: Vec<(&'static str, &'static str)>| {
+            move |name: &str| {
+                vars.iter().find(|&&(var, _)| var == name).map(|&(_, value)| value.into())
+            }
+        };
+        let cmake = OsStr::new("cmake");
+
+        let functions = env(vec![("BASH_FUNC_cmake%%", "() {  /opt/cmake/bin/cmake \"$@\"\n}")]);
+        assert!(shell_alias_hint(cmake, &functions).unwrap().contains("exported shell function"));
+
+        let aliases = env(vec![(SHELL_ALIASES_VAR,
+                                "alias ll='ls -l'\nalias cmake='/opt/cmake/bin/cmake'")]);
+        assert!(shell_alias_hint(cmake, &aliases).unwrap().contains("shell alias"));
+        assert_eq!(shell_alias_hint(OsStr::new("ninja"), &aliases), None);
+        assert_eq!(shell_alias_hint(cmake, &env(vec![])), None);
+    }
+
+    #[test]
+    fn stage0_entries() {
+        let stage0 = "# rustc: 0.1.0\ndate: 2018-05-10\nrustc: 1.27.0\ncargo: beta\n";
+        assert_eq!(stage0_entry(stage0, "date"), Some("2018-05-10"));
+        assert_eq!(stage0_entry(stage0, "rustc"), Some("1.27.0"));
+        assert_eq!(stage0_entry(stage0, "cargo"), Some("beta"));
+        assert_eq!(stage0_entry(stage0, "dev"), None);
+    }
+
+    #[test]
+    fn outdated_stage0_versions() {
+        assert_eq!(outdated_stage0("1.27.0", "1.28.0"), None);
+        assert_eq!(outdated_stage0("1.27.2", "1.28.0"), None);
+        assert_eq!(outdated_stage0("1.28.0", "1.28.0"), None);
+        assert_eq!(outdated_stage0("1.26.2", "1.28.0"), Some((1, 27)));
+        assert_eq!(outdated_stage0("beta", "1.28.0"), None);
+    }
+
+    #[test]
+    fn stage0_sysroot_hosts() {
+        let dir = scratch_dir("stage0_sysroot_hosts");
+        let rustlib = dir.join("lib/rustlib");
+        t!(fs::create_dir_all(rustlib.join("i686-unknown-linux-gnu/codegen-backends")));
+        t!(fs::create_dir_all(rustlib.join("aarch64-unknown-linux-gnu/codegen-backends")));
+        // Only a standard library to cross compile with.
+        t!(fs::create_dir_all(rustlib.join("thumbv7em-none-eabihf/lib")));
+        t!(fs::create_dir_all(rustlib.join("etc")));
+        touch(&rustlib.join("components"));
+        assert_eq!(sysroot_hosts(&dir), ["aarch64-unknown-linux-gnu", "i686-unknown-linux-gnu"]);
+        assert!(sysroot_hosts(&dir.join("missing")).is_empty());
+    }
+
+    #[test]
+    fn pkg_config_overrides() {
+        // Only variables for this test's made up host, so nothing else sees
+        // them.
+        let host = "sanity-pkgconfig-unknown-linux-gnu";
+        env::set_var("PKG_CONFIG_sanity_pkgconfig_unknown_linux_gnu", "/opt/cross/pkg-config");
+        assert_eq!(pkg_config_override(host, "x86_64-unknown-linux-gnu"),
+                   Some(PathBuf::from("/opt/cross/pkg-config")));
+        env::set_var("SANITY_PKGCONFIG_UNKNOWN_LINUX_GNU_OPENSSL_DIR", "/opt/openssl");
+        assert!(openssl_dir_set(host));
+    }
+
+    #[test]
+    fn pkg_config_hosts() {
+        assert!(needs_pkg_config("x86_64-unknown-linux-gnu", false));
+        assert!(needs_pkg_config("aarch64-unknown-linux-musl", false));
+        assert!(!needs_pkg_config("x86_64-unknown-linux-gnu", true));
+        assert!(!needs_pkg_config("x86_64-apple-darwin", false));
+        assert!(!needs_pkg_config("x86_64-pc-windows-gnu", false));
+    }
+
+    #[test]
+    fn lldb_python_dirs() {
+        let dir = scratch_dir("lldb_python_dirs");
+        assert_eq!(lldb_python_dir_problem(&dir.join("missing")), Some("doesn't exist"));
+        assert_eq!(lldb_python_dir_problem(&dir),
+                   Some("doesn't contain the `lldb` Python module"));
+        t!(fs::create_dir_all(dir.join("lldb")));
+        touch(&dir.join("lldb/__init__.py"));
+        assert_eq!(lldb_python_dir_problem(&dir), None);
+    }
+
+    #[test]
+    fn tool_paths() {
+        let cwd = scratch_dir("tool_paths");
+        t!(fs::create_dir_all(cwd.join("bin")));
+        touch(&cwd.join("bin/gcc"));
+        let mut tools = HashMap::new();
+        tools.insert("gcc", "/toolchain/bin/gcc");
+        let mut finder = Finder::with_resolver(Box::new(CannedResolver(tools)));
+
+        assert_eq!(ToolPath::of(Path::new("gcc")), ToolPath::Bare);
+        assert_eq!(ToolPath::of(Path::new("bin/gcc")), ToolPath::Relative);
+        assert_eq!(ToolPath::of(Path::new("./gcc")), ToolPath::Relative);
+        assert_eq!(ToolPath::of(&cwd.join("bin/gcc")), ToolPath::Absolute);
+
+        let resolve = |finder: &mut Finder, path: &str| {
+            resolve_tool_path(finder, &cwd, "target.x.cc", Path::new(path))
+        };
+        assert_eq!(resolve(&mut finder, "bin/gcc").unwrap(), cwd.join("bin/gcc"));
+        assert_eq!(resolve(&mut finder, cwd.join("bin/gcc").to_str().unwrap()).unwrap(),
+                   cwd.join("bin/gcc"));
+        let e = resolve(&mut finder, "bin/clang").unwrap_err();
+        assert_eq!(e.kind, ErrorKind::Config);
+        assert!(e.message.starts_with("target.x.cc is set to bin/clang"));
+        let e = resolve(&mut finder, "clang").unwrap_err();
+        assert!(e.message.ends_with("wasn't found in PATH"));
+        assert_eq!(resolve(&mut finder, "gcc").unwrap(), PathBuf::from("/toolchain/bin/gcc"));
+    }
+
+    #[test]
+    fn wanted_fd_limits() {
+        // The usual default soft limit of Linux fits a few jobs but not many,
+        // and that of macOS none at all.
+        assert!(wanted_fd_limit(4) <= 1024);
+        assert!(wanted_fd_limit(32) > 1024);
+        assert!(wanted_fd_limit(1) > 256);
+    }
+
+    #[test]
+    fn parallelism_problems() {
+        let gib = 1u64 << 30;
+        assert_eq!(parallelism_problem(8, None, 8, None), None);
+        assert_eq!(parallelism_problem(32, Some(8), 8, Some(16 * gib)), None);
+        let problem = parallelism_problem(64, None, 8, Some(16 * gib)).unwrap();
+        assert!(problem.starts_with("building with 64 jobs on 8 logical CPUs and 16.0 GiB of \
+                                     memory"), "{}", problem);
+        let problem = parallelism_problem(16, Some(16), 8, Some(8 * gib)).unwrap();
+        assert!(problem.contains("link up to 16 tools at once"), "{}", problem);
+        assert_eq!(parallelism_problem(16, Some(16), 8, None), None);
+
+        assert_eq!(meminfo_total("MemTotal:       16314588 kB\nMemFree:  1 kB\n"),
+                   Some(16314588 * 1024));
+        assert_eq!(meminfo_total("MemFree:  1 kB\n"), None);
+    }
+
+    #[test]
+    fn unc_paths() {
+        assert!(is_unc_path(r"\\server\share\rust\build"));
+        assert!(is_unc_path("//server/share/rust/build"));
+        assert!(is_unc_path(r"\\?\UNC\server\share\rust"));
+        assert!(!is_unc_path(r"\\?\C:\rust\build"));
+        assert!(!is_unc_path(r"\\.\pipe\rust"));
+        assert!(!is_unc_path(r"C:\rust\build"));
+        assert!(!is_unc_path("/home/rust/build"));
+    }
+
+    #[test]
+    fn default_code_models() {
+        assert_eq!(default_code_model("\nrust_pie pie\n"), Some("pie"));
+        assert_eq!(default_code_model("# 1 \"probe.c\"\nrust_pie none\n"), Some("none"));
+        assert_eq!(default_code_model("rust_pie pic"), Some("pic"));
+        assert_eq!(default_code_model("rust_pie __PIE__"), None);
+        assert_eq!(default_code_model(""), None);
+    }
+
+    #[test]
+    fn path_length_limits() {
+        assert_eq!(path_length_limit(300), None);
+        assert_eq!(path_length_limit(2047), None);
+        assert_eq!(path_length_limit(2048), Some(WINDOWS_LEGACY_PATH_LIMIT));
+        assert_eq!(path_length_limit(30000), Some(WINDOWS_ENV_LIMIT));
+    }
+
+    #[test]
+    fn relative_path_entries_are_found() {
+        let root = env::temp_dir();
+        let path = t!(env::join_paths(&[root.clone(), PathBuf::from("."),
+                                        PathBuf::from("bin"), root.join("bin")]));
+        assert_eq!(relative_path_entries(&path), [PathBuf::from("."), PathBuf::from("bin")]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn caching_shims() {
+        use std::os::unix::fs::symlink;
+
+        let dir = scratch_dir("caching_shims");
+        t!(fs::create_dir_all(dir.join("lib/ccache")));
+        touch(&dir.join("ccache"));
+        touch(&dir.join("gcc"));
+        t!(symlink("../../ccache", dir.join("lib/ccache/gcc")));
+        t!(symlink("gcc", dir.join("cc")));
+        assert_eq!(caching_shim(&dir.join("lib/ccache/gcc")), Some("ccache"));
+        assert_eq!(caching_shim(&dir.join("cc")), None);
+        assert_eq!(caching_shim(&dir.join("gcc")), None);
+    }
+
+    #[test]
+    fn version_constraints() {
+        assert_eq!(parse_version_constraint("7"), Ok(vec![7]));
+        assert_eq!(parse_version_constraint("3.11.4"), Ok(vec![3, 11, 4]));
+        assert_eq!(parse_version_constraint("3.x"), Err("\"x\" isn't a number".to_string()));
+        assert!(parse_version_constraint("").is_err());
+        assert!(parse_version_constraint("1.2.3.4").is_err());
+        assert!(parse_version_constraint("7.").is_err());
+    }
+
+    #[test]
+    fn linker_flavors() {
+        assert_eq!(linker_flavor("GNU ld (GNU Binutils for Ubuntu) 2.30\nCopyright (C) 2018"),
+                   Some(LinkerFlavor::Bfd));
+        assert_eq!(linker_flavor("GNU gold (GNU Binutils for Ubuntu 2.30) 1.15"),
+                   Some(LinkerFlavor::Gold));
+        assert_eq!(linker_flavor("LLD 6.0.0 (compatible with GNU linkers)"),
+                   Some(LinkerFlavor::Lld));
+        assert_eq!(linker_flavor("mold 1.0.0 (compatible with GNU ld)"), Some(LinkerFlavor::Mold));
+        assert_eq!(linker_flavor("collect2 version 7.3.0"), None);
+        assert_eq!(linker_flavor(""), None);
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_match("sccache", "sccache"));
+        assert!(!glob_match("sccache", "ccache"));
+        assert!(glob_match("*-clang-*", "x86_64-linux-clang-wrapper"));
+        assert!(!glob_match("*-clang-*", "clang"));
+        assert!(glob_match("*cache", "sccache"));
+        assert!(glob_match("*cache", "ccache"));
+        assert!(glob_match("python?", "python2"));
+        assert!(!glob_match("python?", "python2.7"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("a*b*c", "aXbYbZ"));
+    }
+
+    #[test]
+    fn glob_patterns_match_programs() {
+        let dir = scratch_dir("glob_patterns_match_programs");
+        let (a, b) = (dir.join("a"), dir.join("b"));
+        t!(fs::create_dir_all(&a));
+        t!(fs::create_dir_all(&b));
+        touch(&a.join("x86_64-clang-wrapper"));
+        touch(&a.join("clang"));
+        touch(&b.join("arm-clang-cc"));
+        touch(&b.join("aarch64-clang-cc"));
+        let dirs = [a.clone(), b.clone(), dir.join("missing")];
+        assert_eq!(programs_matching(&dirs, "*-clang-*"),
+                   [a.join("x86_64-clang-wrapper"), b.join("aarch64-clang-cc"),
+                    b.join("arm-clang-cc")]);
+        assert!(programs_matching(&dirs, "gcc*").is_empty());
+    }
+
+    #[test]
+    fn parse_version_plain() {
+        assert_eq!(parse_version("1.2.3"), Some((1, 2, 3)));
+        assert_eq!(parse_version("10.20"), Some((10, 20, 0)));
+        assert_eq!(parse_version("cmake version 3.11.4"), Some((3, 11, 4)));
+        assert_eq!(parse_version("lldb version 6.0.0"), Some((6, 0, 0)));
+        assert_eq!(parse_version("Python 2.7.15"), Some((2, 7, 15)));
+    }
+
+    #[test]
+    fn parse_version_vendor_suffixes() {
+        assert_eq!(parse_version("git version 2.17.1.windows.2"), Some((2, 17, 1)));
+        assert_eq!(parse_version("git version 2.15.1 (Apple Git-101)"), Some((2, 15, 1)));
+        assert_eq!(parse_version("cmake version 3.11.0-rc2"), Some((3, 11, 0)));
+        assert_eq!(parse_version("cmake version 3.10.2-msys"), Some((3, 10, 2)));
+        assert_eq!(parse_version("Python 2.7.15+"), Some((2, 7, 15)));
+        assert_eq!(parse_version("v8.11.1"), Some((8, 11, 1)));
+        assert_eq!(parse_version("v10.0.0-nightly20180419"), Some((10, 0, 0)));
+    }
+
+    #[test]
+    fn parse_version_compilers() {
+        assert_eq!(parse_version("gcc (GCC) 8.1.1 20180531"), Some((8, 1, 1)));
+        assert_eq!(parse_version("x86_64-linux-gnu-gcc-7 (Ubuntu 7.3.0-16ubuntu3) 7.3.0"),
+                   Some((7, 3, 0)));
+        assert_eq!(parse_version("clang version 6.0.0-1ubuntu2 (tags/RELEASE_600/final)"),
+                   Some((6, 0, 0)));
+        assert_eq!(parse_version("Apple LLVM version 9.1.0 (clang-902.0.39.2)"),
+                   Some((9, 1, 0)));
+    }
+
+    #[test]
+    fn parse_version_debuggers() {
+        let gdb = "GNU gdb (Ubuntu 8.1-0ubuntu3) 8.1.0.20180409-git\n\
+                   Copyright (C) 2018 Free Software Foundation, Inc.\n\
+                   License GPLv3+: GNU GPL version 3 or later";
+        assert_eq!(parse_version(gdb), Some((8, 1, 0)));
+        assert_eq!(parse_version("GNU gdb (GDB) Red Hat Enterprise Linux 7.6.1-110.el7"),
+                   Some((7, 6, 1)));
+        assert_eq!(parse_version("lldb-1000.11.37.1"), Some((1000, 11, 37)));
+        assert_eq!(parse_version("lldb version 7.0.0 (trunk 330464)"), Some((7, 0, 0)));
+    }
+
+    #[test]
+    fn parse_version_nodejs() {
+        assert_eq!(parse_version("8.11.1"), Some((8, 11, 1)));
+        assert_eq!(parse_version("11.0.0-nightly20180601e8b1ba2d9f"), Some((11, 0, 0)));
+        assert_eq!(parse_version("10.0.0-rc.1"), Some((10, 0, 0)));
+        assert!(parse_version("6.14.2").unwrap() < MIN_NODEJS_VERSION);
+        assert!(parse_version("9.11.2").unwrap() >= MIN_NODEJS_VERSION);
+    }
+
+    #[test]
+    fn gdb_versions() {
+        let banner = "GNU gdb (Ubuntu 8.1-0ubuntu3) 8.1.0.20180409-git\n\
+                      Copyright (C) 2018 Free Software Foundation, Inc.\n\
+                      License GPLv3+: GNU GPL version 3 or later\n";
+        assert_eq!(gdb_version_line(banner),
+                   Some("GNU gdb (Ubuntu 8.1-0ubuntu3) 8.1.0.20180409-git".to_string()));
+        assert_eq!(gdb_version_line("\n"), None);
+        assert_eq!(gdb_version(&gdb_version_line(banner).unwrap()), Some((8, 1, 0)));
+        assert_eq!(gdb_version("GNU gdb (GDB; openSUSE 13.2) 7.8"), Some((7, 8, 0)));
+        assert_eq!(gdb_version("GNU gdb (GDB) Red Hat Enterprise Linux 7.6.1-110.el7"),
+                   Some((7, 6, 1)));
+        assert_eq!(gdb_version("GNU gdb (Ubuntu/Linaro 7.4-2012.04-0ubuntu2.1) 7.4-2012.04"),
+                   Some((7, 4, 0)));
+        assert_eq!(gdb_version("GNU gdb (GDB) CentOS (7.0.1-45.el5.centos)"), Some((7, 0, 1)));
+        assert!(gdb_version("GNU gdb (GDB) Red Hat Enterprise Linux (7.2-90.el6)").unwrap() <
+                MIN_GDB_VERSION);
+        assert_eq!(gdb_version("GNU gdb"), None);
+        assert_eq!(gdb_unsupported("GNU gdb (GDB) 8.1"), None);
+        assert_eq!(gdb_unsupported("GNU gdb"), None);
+        assert!(gdb_unsupported("GNU gdb (GDB) 7.2").unwrap().starts_with("gdb 7.2.0 is older"));
+    }
+
+    #[test]
+    fn cmake_version_output() {
+        assert_eq!(cmake_version("cmake version 3.11.4\n\nCMake suite maintained and \
+                                  supported by Kitware (kitware.com/cmake).\n"),
+                   Some((3, 11, 4)));
+        assert_eq!(cmake_version("cmake version 3.20.0-rc2"), Some((3, 20, 0)));
+        assert_eq!(cmake_version("cmake3 version 3.6.1"), Some((3, 6, 1)));
+        assert_eq!(cmake_version("cmake version 2.8.12.2"), Some((2, 8, 12)));
+        assert!(cmake_version("cmake version 2.8.12.2").unwrap() < MIN_CMAKE_VERSION);
+        assert!(cmake_version("cmake version 3.4.3").unwrap() >= MIN_CMAKE_VERSION);
+        assert_eq!(cmake_version(""), None);
+        assert_eq!(cmake_version("Usage: cmake 3.11.4 [options] <path-to-source>"), None);
+    }
+
+    #[test]
+    fn parse_version_garbage() {
+        assert_eq!(parse_version(""), None);
+        assert_eq!(parse_version("command not found"), None);
+        assert_eq!(parse_version("version 3"), None);
+        assert_eq!(parse_version("x86_64"), None);
+        assert_eq!(parse_version("1."), None);
+        assert_eq!(parse_version("99999999999999999999.1"), None);
+    }
+
+    #[test]
+    fn library_search_dirs_parses_gcc_output() {
+        let out = "install: /usr/lib/gcc/x86_64-linux-musl/6.4.0/\n\
+                   programs: =/usr/libexec/gcc/x86_64-linux-musl/6.4.0/\n\
+                   libraries: =/usr/lib/gcc/x86_64-linux-musl/6.4.0/:/lib/:/usr/lib/\n";
+        assert_eq!(library_search_dirs(out), vec![
+            PathBuf::from("/usr/lib/gcc/x86_64-linux-musl/6.4.0/"),
+            PathBuf::from("/lib/"),
+            PathBuf::from("/usr/lib/"),
+        ]);
+        assert!(library_search_dirs("programs: =/usr/bin").is_empty());
+    }
+
+    #[test]
+    fn missing_static_libs_in_toolchain() {
+        let root = scratch_dir("missing_static_libs_in_toolchain");
+        let gcc = root.join("lib/gcc/x86_64-linux-musl/6.4.0");
+        let lib = root.join("lib");
+        t!(fs::create_dir_all(&gcc));
+        touch(&gcc.join("libgcc.a"));
+        touch(&gcc.join("libgcc_eh.a"));
+        // A shared libstdc++ doesn't help a static build.
+        touch(&lib.join("libstdc++.so"));
+        let dirs = vec![gcc.clone(), lib.clone()];
+        assert_eq!(missing_libs(&dirs, STATIC_HOST_LIBS), vec!["libstdc++.a"]);
+
+        touch(&lib.join("libstdc++.a"));
+        assert!(missing_libs(&dirs, STATIC_HOST_LIBS).is_empty());
+        assert_eq!(missing_libs(&[], STATIC_HOST_LIBS), STATIC_HOST_LIBS.to_vec());
+    }
+
+    #[test]
+    fn first_of_prefers_earlier_names() {
+        let dir = scratch_dir("first_of_prefers_earlier_names");
+        touch(&dir.join("ninja"));
+        touch(&dir.join("ninja-build"));
+        let mut finder = Finder::with_path(dir.clone().into_os_string());
+        assert_eq!(finder.first_of(&["ninja-build", "ninja"]), Some(dir.join("ninja-build")));
+        assert_eq!(finder.first_of(&["ninja", "ninja-build"]), Some(dir.join("ninja")));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn git_wrappers_without_submodule() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch_dir("git_wrappers_without_submodule");
+        let fake_git = |name: &str, script: &str| {
+            let git = dir.join(name);
+            t!(t!(File::create(&git)).write_all(script.as_bytes()));
+            t!(fs::set_permissions(&git, fs::Permissions::from_mode(0o755)));
+            git
+        };
+        let exec = dir.join("exec");
+        t!(fs::create_dir_all(&exec));
+        touch(&exec.join("git-submodule"));
+        let full = fake_git("full", &format!("#!/bin/sh\necho {}\n", exec.display()));
+        assert!(git_has_submodule(&full));
+        let builtin = fake_git("builtin", "#!/bin/sh\n\
+            [ \"$1\" = submodule ] && echo 'usage: git submodule [--quiet]' && exit 129\n\
+            exit 1\n");
+        assert!(git_has_submodule(&builtin));
+        let wrapper = fake_git("wrapper", "#!/bin/sh\n\
+            echo \"git: '$1' is not a git command. See 'git --help'.\" >&2\nexit 1\n");
+        assert!(!git_has_submodule(&wrapper));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn find_python_skips_interpreters_which_fail() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch_dir("find_python_skips_interpreters_which_fail");
+        let fake_python = |name: &str, script: &str| {
+            let python = dir.join(name);
+            t!(t!(File::create(&python)).write_all(script.as_bytes()));
+            t!(fs::set_permissions(&python, fs::Permissions::from_mode(0o755)));
+        };
+        fake_python("python2", "#!/bin/sh\necho 'Python 2.7.15' >&2\n");
+        let mut finder = Finder::with_path(dir.clone().into_os_string());
+        assert_eq!(find_python(&mut finder), Some(dir.join("python2")));
+
+        fake_python("python3", "#!/bin/sh\necho 'Python 3.6.5'\n");
+        fake_python("python", "#!/bin/sh\nexit 1\n");
+        let mut finder = Finder::with_path(dir.clone().into_os_string());
+        assert_eq!(find_python(&mut finder), Some(dir.join("python3")));
+
+        fake_python("python3", "#!/bin/sh\nexec /nonexistent/python3.6 \"$@\"\n");
+        let mut finder = Finder::with_path(dir.clone().into_os_string());
+        assert_eq!(find_python(&mut finder), Some(dir.join("python2")));
+    }
+
+    #[test]
+    fn must_have_one_of_caches_the_canonical_name() {
+        let mut tools = HashMap::new();
+        tools.insert("ninja-build", "/usr/bin/ninja-build");
+        let mut finder = Finder::with_resolver(Box::new(CannedResolver(tools)));
+        assert_eq!(finder.must_have_one_of(&["ninja", "ninja-build"]).unwrap(),
+                   PathBuf::from("/usr/bin/ninja-build"));
+        assert_eq!(finder.maybe_have("ninja"), Some(PathBuf::from("/usr/bin/ninja-build")));
+
+        let err = finder.must_have_one_of(&["node", "nodejs"]).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::MissingTool);
+        assert_eq!(err.subject, Some("node".to_string()));
+        assert_eq!(err.to_string(), "\n\ncouldn't find required command \"node\", under any of \
+                                     the names \"node\", \"nodejs\"\n\n");
+    }
+
+    #[test]
+    fn first_of_skips_missing_names() {
+        let dir = scratch_dir("first_of_skips_missing_names");
+        touch(&dir.join("nodejs"));
+        let mut finder = Finder::with_path(dir.clone().into_os_string());
+        assert_eq!(finder.first_of(&["node", "nodejs"]), Some(dir.join("nodejs")));
+        assert_eq!(finder.first_of(&["node"]), None);
+        assert_eq!(finder.first_of(&[]), None);
+    }
+
+    #[test]
+    fn first_of_searches_path_in_order() {
+        let a = scratch_dir("first_of_searches_path_in_order_a");
+        let b = scratch_dir("first_of_searches_path_in_order_b");
+        touch(&a.join("python2"));
+        touch(&b.join("python2.7"));
+        touch(&b.join("python2"));
+        let path = t!(env::join_paths(&[&a, &b]));
+        let mut finder = Finder::with_path(path);
+        // Candidate order wins over `PATH` order.
+        assert_eq!(finder.first_of(&["python2.7", "python2"]), Some(b.join("python2.7")));
+        assert_eq!(finder.first_of(&["python2"]), Some(a.join("python2")));
+    }
+
+    #[test]
+    fn wasm_lld_targets() {
+        assert!(links_with_wasm_lld("wasm32-unknown-unknown"));
+        assert!(!links_with_wasm_lld("wasm32-experimental-emscripten"));
+        assert!(!links_with_wasm_lld("asmjs-unknown-emscripten"));
+        assert!(!links_with_wasm_lld("x86_64-unknown-linux-gnu"));
+    }
+
+    #[test]
+    fn android_api_levels() {
+        assert_eq!(android_min_api("aarch64-linux-android"), Some(21));
+        assert_eq!(android_min_api("x86_64-linux-android"), Some(21));
+        assert_eq!(android_min_api("armv7-linux-androideabi"), Some(14));
+        assert_eq!(android_min_api("i686-linux-android"), Some(14));
+        assert_eq!(android_min_api("x86_64-unknown-linux-gnu"), None);
+
+        assert_eq!(android_api_level("int foo;\nrust_android_api 21\n"), Some(21));
+        // Without `--api` the level is left as "future" (10000).
+        assert_eq!(android_api_level("rust_android_api 10000\n"), Some(10000));
+        assert_eq!(android_api_level("rust_android_api __ANDROID_API__\n"), None);
+        assert_eq!(android_api_level(""), None);
+    }
+
+    #[test]
+    fn pointer_widths() {
+        assert!(is_32bit("i686-unknown-linux-gnu"));
+        assert!(is_32bit("armv7-unknown-linux-gnueabihf"));
+        assert!(is_32bit("mipsel-unknown-linux-gnu"));
+        assert!(!is_32bit("x86_64-unknown-linux-gnu"));
+        assert!(!is_32bit("aarch64-unknown-linux-gnu"));
+        assert!(!is_32bit("powerpc64le-unknown-linux-gnu"));
+        assert!(!is_32bit("s390x-unknown-linux-gnu"));
+        assert!(!is_32bit("sparcv9-sun-solaris"));
+    }
+
+    #[test]
+    fn toml_strings() {
+        assert_eq!(toml_string("/usr/bin/cc"), "\"/usr/bin/cc\"");
+        assert_eq!(toml_string("C:\\Program Files\\\"LLVM\""),
+                   "\"C:\\\\Program Files\\\\\\\"LLVM\\\"\"");
+        assert_eq!(toml_string("a\tb\u{1}"), "\"a\\tb\\u0001\"");
+    }
+
+    #[test]
+    fn pinned_config_snippet() {
+        let mut config = Config::default();
+        config.build = INTERNER.intern_str("x86_64-unknown-linux-gnu");
+        config.hosts = vec![config.build];
+        config.targets = vec![config.build, INTERNER.intern_str("thumbv7em-none-eabihf")];
+        config.ignore_git = true;
+        config.python = Some(PathBuf::from("/usr/bin/python2.7"));
+        config.nodejs = Some(PathBuf::from("/usr/bin/node"));
+        config.target_config.entry(INTERNER.intern_str("thumbv7em-none-eabihf"))
+            .or_insert(Default::default()).no_std = true;
+        let mut build = Build::bare(config);
+        build.nodejs_version = Some("8.11.2".to_string());
+        let pinned = pinned_config(&build);
+        assert!(pinned.contains("[build]\nbuild = \"x86_64-unknown-linux-gnu\"\n\
+                                 host = [\"x86_64-unknown-linux-gnu\"]\n\
+                                 target = [\"x86_64-unknown-linux-gnu\", \
+                                 \"thumbv7em-none-eabihf\"]\n"), "{}", pinned);
+        assert!(pinned.contains("python = \"/usr/bin/python2.7\"\n"), "{}", pinned);
+        assert!(pinned.contains("nodejs = \"/usr/bin/node\"  # 8.11.2\n"), "{}", pinned);
+        assert!(pinned.contains("[target.thumbv7em-none-eabihf]\nno-std = true\n"),
+                "{}", pinned);
+        assert!(pinned.contains("[target.x86_64-unknown-linux-gnu]\n"), "{}", pinned);
+    }
+
+    #[test]
+    fn truthy_values() {
+        for value in &["1", "true", "TRUE", "yes", "on", " 1\n"] {
+            assert!(is_truthy(Some(value.into())), "{:?}", value);
+        }
+        for value in &["", "0", "false", "no", "off", "2"] {
+            assert!(!is_truthy(Some(value.into())), "{:?}", value);
+        }
+        assert!(!is_truthy(None));
+    }
+
+    #[test]
+    fn tool_report_json() {
+        let mut config = Config::default();
+        config.build = INTERNER.intern_str("x86_64-unknown-linux-gnu");
+        config.ignore_git = true;
+        let build = Build::bare(config);
+        let mut finder = Finder::with_resolver(Box::new(CannedResolver(HashMap::new())));
+        assert_eq!(tool_report(&build, &finder), "{}\n");
+
+        let mut config = Config::default();
+        config.build = INTERNER.intern_str("x86_64-unknown-linux-gnu");
+        config.ignore_git = true;
+        config.python = Some(PathBuf::from("/usr/bin/python3"));
+        let build = Build::bare(config);
+        finder.cache.insert("cmake".into(), Some(PathBuf::from("/usr/bin/cmake")));
+        finder.cache.insert("ninja".into(), None);
+        finder.versions.insert(PathBuf::from("/usr/bin/cmake"), "cmake version 3.11.4".into());
+        let report: serde_json::Value = t!(serde_json::from_str(&tool_report(&build, &finder)));
+        assert_eq!(report.as_object().unwrap().len(), 2);
+        assert_eq!(report["cmake"]["path"], "/usr/bin/cmake");
+        assert_eq!(report["cmake"]["version"], "cmake version 3.11.4");
+        assert_eq!(report["python"]["path"], "/usr/bin/python3");
+        assert!(report["python"]["version"].is_null());
+    }
+
+    /// Makes a little-endian ELF64 object whose `.debug_info` starts with
+    /// `unit`.
+    fn elf_with_debug_info(unit: &[u8]) -> Vec<u8> {
+        let names = b"\0.shstrtab\0.debug_info\0";
+        let mut elf = b"\x7fELF\x02\x01\x01\x00".to_vec();
+        elf.resize(64, 0);
+        let strtab = elf.len();
+        elf.extend_from_slice(names);
+        let info = elf.len();
+        elf.extend_from_slice(unit);
+        let shoff = elf.len();
+        let put = |elf: &mut Vec<u8>, n: u64, len: usize| {
+            for i in 0..len {
+                elf.push((n >> (8 * i)) as u8);
+            }
+        };
+        // The null section, .shstrtab and .debug_info.
+        for &(name, offset, size) in &[(0, 0, 0), (1, strtab, names.len()),
+                                       (11, info, unit.len())] {
+            put(&mut elf, name, 4);
+            put(&mut elf, 0, 4);
+            elf.extend_from_slice(&[0; 16]);
+            put(&mut elf, offset as u64, 8);
+            put(&mut elf, size as u64, 8);
+            elf.extend_from_slice(&[0; 24]);
+        }
+        for &(at, n, len) in &[(0x28, shoff as u64, 8), (0x3a, 64, 2), (0x3c, 3, 2),
+                               (0x3e, 1, 2)] {
+            let mut field = Vec::new();
+            put(&mut field, n, len);
+            elf[at..at + len].copy_from_slice(&field);
+        }
+        elf
+    }
+
+    #[test]
+    fn dwarf_versions() {
+        assert_eq!(dwarf_version(&elf_with_debug_info(b"\x20\0\0\0\x04\0")), Some(4));
+        assert_eq!(dwarf_version(&elf_with_debug_info(b"\x20\0\0\0\x05\0")), Some(5));
+        assert_eq!(dwarf_version(&elf_with_debug_info(b"\xff\xff\xff\xff\x20\0\0\0\0\0\0\0\
+                                                        \x03\0")),
+                   Some(3));
+        assert_eq!(dwarf_version(&elf_with_debug_info(b"")), None);
+        assert_eq!(dwarf_version(b"!<arch>\n"), None);
+    }
+
+    #[test]
+    fn dwarf_version_of_garbage() {
+        let elf = elf_with_debug_info(b"\x20\0\0\0\x04\0");
+        let shoff = elf.len() - 3 * 64;
+        let corrupt = |at: usize, len: usize| {
+            let mut elf = elf.clone();
+            for byte in &mut elf[at..at + len] {
+                *byte = 0xff;
+            }
+            dwarf_version(&elf)
+        };
+        let mut truncated = elf.clone();
+        truncated.truncate(shoff + 64 + 40);
+        assert_eq!(dwarf_version(&truncated), None);
+        // e_shoff, and e_shentsize with e_shstrndx.
+        assert_eq!(corrupt(0x28, 8), None);
+        assert_eq!(corrupt(0x3a, 2), None);
+        assert_eq!(corrupt(0x3e, 2), None);
+        // The size of .shstrtab, and the offset and size of .debug_info.
+        assert_eq!(corrupt(shoff + 64 + 32, 8), None);
+        assert_eq!(corrupt(shoff + 128 + 24, 8), None);
+        assert_eq!(corrupt(shoff + 128 + 32, 8), None);
+    }
+
+    #[test]
+    fn elf_machines() {
+        let mut header = b"\x7fELF\x02\x01\x01\x00".to_vec();
+        header.resize(20, 0);
+        header[18] = 62;
+        assert_eq!(elf_machine(&header), Some(62));
+        // Big-endian, like powerpc64.
+        header[5] = 2;
+        header[18] = 0;
+        header[19] = 21;
+        assert_eq!(elf_machine(&header), Some(21));
+        assert_eq!(elf_machine(&header[..10]), None);
+        assert_eq!(elf_machine(b"!<arch>\nxxxxxxxxxxxxxxxxxxxx"), None);
+
+        assert_eq!(target_elf_machine("x86_64-unknown-linux-gnu"), Some(62));
+        assert_eq!(target_elf_machine("i686-unknown-freebsd"), Some(3));
+        assert_eq!(target_elf_machine("armv7-unknown-linux-gnueabihf"), Some(40));
+        assert_eq!(target_elf_machine("powerpc64le-unknown-linux-gnu"), Some(21));
+        assert_eq!(target_elf_machine("x86_64-apple-darwin"), None);
+        assert_eq!(target_elf_machine("x86_64-pc-windows-gnu"), None);
+    }
+
+    #[test]
+    fn archive_elf_machines() {
+        let dir = scratch_dir("archive_elf_machines");
+        let member = |name: &str, data: &[u8]| {
+            let mut member = format!("{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+                                     name, 0, 0, 0, 644, data.len()).into_bytes();
+            member.extend_from_slice(data);
+            if data.len() % 2 == 1 {
+                member.push(b'\n');
+            }
+            member
+        };
+        let mut object = b"\x7fELF\x02\x01\x01\x00".to_vec();
+        object.resize(64, 0);
+        object[18] = 183;
+        let mut archive = b"!<arch>\n".to_vec();
+        archive.extend(member("/", b"\x00\x00\x00\x01\x00\x00\x00\x48abs"));
+        archive.extend(member("abs.o/", &object));
+        let libc = dir.join("libc.a");
+        t!(t!(File::create(&libc)).write_all(&archive));
+        assert_eq!(archive_elf_machine(&libc), Some(183));
+        assert_eq!(elf_machine_name(183), "aarch64");
+        assert_eq!(elf_machine_name(62), "x86_64");
+        assert_eq!(elf_machine_name(9999), "ELF machine 9999");
+
+        t!(t!(File::create(&libc)).write_all(b"!<arch>\n"));
+        assert_eq!(archive_elf_machine(&libc), None);
+        assert_eq!(archive_elf_machine(&dir.join("missing.a")), None);
+    }
+
+    #[test]
+    fn sccache_versions() {
+        assert!(is_sccache("sccache"));
+        assert!(is_sccache("/home/me/.cargo/bin/sccache"));
+        assert!(!is_sccache("ccache"));
+        assert!(!is_sccache("/usr/lib/sccache/ccache"));
+        assert_eq!(sccache_version("sccache 0.2.7"), Some((0, 2, 7)));
+        assert_eq!(sccache_version("sccache 0.2.8-alpha.0\n"), Some((0, 2, 8)));
+        assert_eq!(sccache_version("ccache version 3.4.2"), None);
+        assert_eq!(sccache_version(""), None);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn output_within_kills_hung_commands() {
+        let out = t!(output_within(Command::new("sh").args(&["-c", "echo out; echo err >&2"]),
+                                   Duration::from_secs(60)));
+        assert!(out.status.success());
+        assert_eq!(out.stdout, b"out\n");
+        assert_eq!(out.stderr, b"err\n");
+
+        let start = Instant::now();
+        let err = output_within(Command::new("sleep").arg("60"), Duration::from_millis(100))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(30));
+    }
+
+    #[test]
+    fn sha256_outputs() {
+        let hash = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        assert_eq!(parse_sha256(&format!("{}  /usr/bin/cmake\n", hash)),
+                   Some(hash.to_string()));
+        let spaced = hash.as_bytes().chunks(2)
+            .map(|byte| String::from_utf8_lossy(byte).to_uppercase())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let certutil = format!("SHA256 hash of C:\\cmake\\bin\\cmake.exe:\r\n{}\r\n\
+                                CertUtil: -hashfile command completed successfully.\r\n",
+                               spaced);
+        assert_eq!(parse_sha256(&certutil), Some(hash.to_string()));
+        assert_eq!(parse_sha256("sha256sum: /usr/bin/cmake: No such file\n"), None);
+    }
+
+    #[test]
+    fn clock_skews() {
+        let now = SystemTime::now();
+        let later = now + Duration::from_millis(20);
+        assert_eq!(clock_skew(now, now + Duration::from_millis(10), later), None);
+        // Timestamps are often truncated to whole seconds.
+        assert_eq!(clock_skew(now, now - Duration::from_secs(1), later), None);
+        assert_eq!(clock_skew(now, later + Duration::from_secs(90), later),
+                   Some((Duration::from_secs(90), true)));
+        assert_eq!(clock_skew(now, now - Duration::from_secs(3600), later),
+                   Some((Duration::from_secs(3600), false)));
+    }
+
+    #[test]
+    fn sanity_profiles() {
+        for name in MINIMAL_CHECKS {
+            assert!(CHECKS.iter().any(|c| c.name == *name), "unknown check {}", name);
+        }
+        let mut config = Config::default();
+        assert_eq!(Profile::from_config(&config).unwrap(), Profile::Full);
+        config.sanity_profile = Some("minimal".to_string());
+        let profile = Profile::from_config(&config).unwrap();
+        assert!(profile.includes("build-dir"));
+        assert!(!profile.includes("cmake"));
+        assert!(!profile.includes("cxx-compilers"));
+        assert_eq!(Profile::of("cmake"), Profile::Full);
+        config.sanity_profile = Some("docs".to_string());
+        assert_eq!(Profile::from_config(&config).unwrap_err().kind, ErrorKind::Config);
+    }
+
+    #[test]
+    fn checks_come_after_their_dependencies() {
+        for (i, c) in CHECKS.iter().enumerate() {
+            for name in c.after {
+                assert!(CHECKS[..i].iter().any(|earlier| earlier.name == *name),
+                        "{} comes after {}, which isn't an earlier check", c.name, name);
+            }
+        }
+    }
+
+    #[test]
+    fn check_plans() {
+        let mut config = Config::default();
+        config.dry_run = true;
+        config.deny_sanity_warnings = vec!["relative-path".to_string()];
+        let plan = check_plan(&config, Profile::Full);
+        assert_eq!(plan, check_plan(&config, Profile::Full));
+        let plan: serde_json::Value = t!(serde_json::from_str(&plan));
+        assert_eq!(plan["profile"], "full");
+        assert_eq!(plan["dry-run"], true);
+        let checks = plan["checks"].as_array().unwrap();
+        let check = |name: &str| checks.iter().find(|c| c["name"] == name);
+        let relative = check("relative-path").unwrap();
+        assert_eq!(relative["description"],
+                   "PATH doesn't contain entries relative to the working directory");
+        assert_eq!(relative["severity"], "warning");
+        assert_eq!(relative["cost"], "static");
+        assert_eq!(relative["after"], serde_json::Value::Array(Vec::new()));
+        assert_eq!(relative["denied"], true);
+        assert_eq!(check("cross-ar").unwrap()["after"][0], "c-compilers");
+        // Probes don't run in a dry run.
+        assert!(check("cc-c99").is_none());
+        let minimal = check_plan(&config, Profile::Minimal);
+        let minimal: serde_json::Value = t!(serde_json::from_str(&minimal));
+        assert!(minimal["checks"].as_array().unwrap().iter().all(|c| c["name"] != "cmake"));
+    }
+
+    #[test]
+    fn list_checks_stops_without_exiting() {
+        let mut config = Config::default();
+        config.ignore_git = true;
+        config.list_checks = true;
+        let mut build = Build::bare(config);
+        match try_check(&mut build, None) {
+            Ok(Checked::Stop { output, code }) => {
+                assert_eq!(code, 0);
+                assert_eq!(output.lines().count(), CHECKS.len());
+            }
+            other => panic!("expected --list-checks to stop, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn result_jsons() {
+        assert_eq!(result_json(&Ok(())), "{\"ok\":true,\"exit-code\":0}\n");
+
+        let mut err = SanityError::new(ErrorKind::MissingTool,
+                                       "couldn't find \"cmake\"\n".to_string()).about("cmake");
+        err.check = "cmake";
+        assert_eq!(result_json::<()>(&Err(err)),
+                   "{\"ok\":false,\"exit-code\":2,\"kind\":\"missing-tool\",\
+                    \"check\":\"cmake\",\"subject\":\"cmake\",\
+                    \"message\":\"couldn't find \\\"cmake\\\"\"}\n");
+
+        let err = SanityError::new(ErrorKind::Config, "bad\u{1}\u{1f600}".to_string());
+        let json: serde_json::Value = t!(serde_json::from_str(&result_json::<()>(&Err(err))));
+        assert_eq!(json["check"], "");
+        assert!(json["subject"].is_null());
+        assert_eq!(json["message"], "bad\u{1}\u{1f600}");
+    }
+
+    #[test]
+    fn conflicting_options() {
+        let mut config = Config::default();
+        config.llvm_enabled = true;
+        config.lld_enabled = true;
+        config.sanitizers = true;
+        validate_conflicts(&config).unwrap();
+
+        config.llvm_enabled = false;
+        let err = validate_conflicts(&config).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Config);
+        assert!(err.message.starts_with("llvm.enabled = false conflicts with rust.lld = true"),
+                "{}", err.message);
+
+        config.lld_enabled = false;
+        let err = validate_conflicts(&config).unwrap_err();
+        assert!(err.message.contains("build.sanitizers"), "{}", err.message);
+
+        config.sanitizers = false;
+        config.sanity_diff = true;
+        validate_conflicts(&config).unwrap();
+        config.sanity_only = true;
+        assert!(validate_conflicts(&config).is_err());
+    }
+
+    #[test]
+    fn llvm_backends() {
+        assert_eq!(llvm_backend("x86_64-unknown-linux-gnu"), Some("X86"));
+        assert_eq!(llvm_backend("thumbv7em-none-eabihf"), Some("ARM"));
+        assert_eq!(llvm_backend("riscv32imac-unknown-none-elf"), Some("RISCV"));
+        assert_eq!(llvm_backend("wasm32-unknown-emscripten"), None);
+        assert_eq!(llvm_backend("le32-unknown-nacl"), None);
+
+        let targets = ["x86_64-unknown-linux-gnu", "armv7-unknown-linux-gnueabihf",
+                       "riscv32imac-unknown-none-elf", "arm-unknown-linux-gnueabi"];
+        assert_eq!(missing_llvm_backends(&targets, &["X86", "ARM", "RISCV"]), vec![]);
+        assert_eq!(missing_llvm_backends(&targets, &["X86"]),
+                   vec![("ARM", vec!["armv7-unknown-linux-gnueabihf",
+                                     "arm-unknown-linux-gnueabi"]),
+                        ("RISCV", vec!["riscv32imac-unknown-none-elf"])]);
     }
 }
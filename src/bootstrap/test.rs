@@ -952,6 +952,13 @@ impl Step for Compiletest {
                 };
             }
 
+            if mode == "debuginfo-gdb" {
+                if let Some(ref why) = builder.gdb_unsupported {
+                    builder.info(&format!("Skipping debuginfo-gdb tests: {}", why));
+                    return;
+                }
+            }
+
             builder.ensure(dist::DebuggerScripts {
                 sysroot: builder.sysroot(compiler),
                 host: target,
@@ -1595,7 +1602,7 @@ impl Step for Crate {
             }
             Mode::Rustc => {
                 builder.ensure(compile::Rustc { compiler, target });
-                compile::rustc_cargo(builder, &mut cargo);
+                compile::rustc_cargo(builder, target, &mut cargo);
             }
             _ => panic!("can only test libraries"),
         };